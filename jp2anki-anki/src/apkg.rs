@@ -0,0 +1,382 @@
+use std::{fs, io::{Write, Seek}, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+use jp2anki::AnalyzerResult;
+use rusqlite::{Connection, params};
+use serde_json::json;
+use sha1::{Sha1, Digest};
+use zip::{ZipWriter, write::FileOptions, CompressionMethod};
+
+use crate::{AudioCache, Result, model::NoteModel};
+
+// Schema of a version 11 collection, which every Anki release can import
+const COLLECTION_SCHEMA: &str = "
+CREATE TABLE col (
+    id integer primary key, crt integer not null, mod integer not null,
+    scm integer not null, ver integer not null, dty integer not null,
+    usn integer not null, ls integer not null, conf text not null,
+    models text not null, decks text not null, dconf text not null,
+    tags text not null
+);
+CREATE TABLE notes (
+    id integer primary key, guid text not null, mid integer not null,
+    mod integer not null, usn integer not null, tags text not null,
+    flds text not null, sfld integer not null, csum integer not null,
+    flags integer not null, data text not null
+);
+CREATE TABLE cards (
+    id integer primary key, nid integer not null, did integer not null,
+    ord integer not null, mod integer not null, usn integer not null,
+    type integer not null, queue integer not null, due integer not null,
+    ivl integer not null, factor integer not null, reps integer not null,
+    lapses integer not null, left integer not null, odue integer not null,
+    odid integer not null, flags integer not null, data text not null
+);
+CREATE TABLE revlog (
+    id integer primary key, cid integer not null, usn integer not null,
+    ease integer not null, ivl integer not null, lastIvl integer not null,
+    factor integer not null, time integer not null, type integer not null
+);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+";
+
+const FIELD_SEPARATOR: &str = "\x1f";
+
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub guid: String,
+    pub fields: Vec<String>,
+    pub tags: Vec<String>,
+    /// Files of the audio cache that the fields refer to
+    pub media: Vec<String>
+}
+
+enum Media {
+    Data(Vec<u8>),
+    File(PathBuf)
+}
+
+pub struct Deck {
+    pub id: i64,
+    pub name: String,
+    pub description: String,
+    pub model: NoteModel,
+    notes: Vec<Note>,
+    media: Vec<(String, Media)>,
+    audio_cache: Option<AudioCache>
+}
+
+impl Deck {
+    pub fn new(name: &str) -> Self {
+        Deck::with_model(name, NoteModel::default())
+    }
+
+    pub fn with_model(name: &str, model: NoteModel) -> Self {
+        // Derive the id from the name so that re-exports land in the same deck
+        let hash = Sha1::digest(name.as_bytes());
+        let id = u64::from_be_bytes(hash[..8].try_into().unwrap()) >> 12;
+        Deck {
+            id: id as i64,
+            name: name.to_owned(),
+            description: String::new(),
+            model,
+            notes: Vec::new(),
+            media: Vec::new(),
+            audio_cache: None
+        }
+    }
+
+    /// Embeds cached pronunciation audio in the notes added from now on
+    pub fn set_audio_cache(&mut self, cache: AudioCache) {
+        self.audio_cache = Some(cache);
+    }
+
+    pub fn add_note(&mut self, note: Note) {
+        if let Some(cache) = &self.audio_cache {
+            for filename in &note.media {
+                if !self.media.iter().any(|(name, _)| name == filename) {
+                    let path = cache.dir().join(filename);
+                    self.media.push((filename.clone(), Media::File(path)));
+                }
+            }
+        }
+        self.notes.push(note);
+    }
+
+    pub fn add_results<'a>(&mut self, results: impl IntoIterator<Item = &'a AnalyzerResult>) {
+        for result in results {
+            for note in self.model.notes_with_audio(result, self.audio_cache.as_ref()) {
+                self.add_note(note);
+            }
+        }
+    }
+
+    pub fn add_media(&mut self, filename: &str, data: Vec<u8>) {
+        self.media.push((filename.to_owned(), Media::Data(data)));
+    }
+
+    pub fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+
+    pub fn write_apkg<W: Write + Seek>(&self, out: W) -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let collection_path = dir.path().join("collection.anki2");
+        {
+            let conn = Connection::open(&collection_path)?;
+            self.write_collection(&conn)?;
+        }
+
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        let mut zip = ZipWriter::new(out);
+        zip.start_file("collection.anki2", options)?;
+        zip.write_all(&fs::read(&collection_path)?)?;
+
+        // Media files are stored under their index, the "media" map restores their names
+        let media_map: serde_json::Map<String, serde_json::Value> = self.media.iter()
+            .enumerate()
+            .map(|(i, (name, _))| (i.to_string(), name.clone().into()))
+            .collect();
+        zip.start_file("media", options)?;
+        serde_json::to_writer(&mut zip, &media_map)?;
+        for (i, (_, media)) in self.media.iter().enumerate() {
+            zip.start_file(i.to_string(), options)?;
+            match media {
+                Media::Data(data) => zip.write_all(data)?,
+                Media::File(path) => zip.write_all(&fs::read(path)?)?
+            }
+        }
+
+        zip.finish()?;
+        Ok(())
+    }
+
+    fn write_collection(&self, conn: &Connection) -> Result<()> {
+        let now_ms = now_millis();
+        let now = now_ms / 1000;
+
+        conn.execute_batch(COLLECTION_SCHEMA)?;
+        conn.execute(
+            "INSERT INTO col VALUES (1, ?, ?, ?, 11, 0, 0, 0, ?, ?, ?, ?, '{}')",
+            params![
+                now, now_ms, now_ms,
+                self.conf_json().to_string(),
+                self.models_json(now).to_string(),
+                self.decks_json(now).to_string(),
+                dconf_json().to_string()
+            ]
+        )?;
+
+        let mut insert_note = conn.prepare("INSERT INTO notes VALUES (?, ?, ?, ?, -1, ?, ?, ?, ?, 0, '')")?;
+        let mut insert_card = conn.prepare("INSERT INTO cards VALUES (?, ?, ?, ?, ?, -1, 0, 0, ?, 0, 0, 0, 0, 0, 0, 0, 0, '')")?;
+        let mut card_id = now_ms;
+        for (i, note) in self.notes.iter().enumerate() {
+            let id = now_ms + i as i64;
+            let sort_field = note.fields.first().map(String::as_str).unwrap_or_default();
+            let tags = if note.tags.is_empty() {
+                String::new()
+            } else {
+                format!(" {} ", note.tags.join(" "))
+            };
+            insert_note.execute(params![
+                id, note.guid, self.model.id, now, tags,
+                note.fields.join(FIELD_SEPARATOR),
+                strip_html(sort_field), field_checksum(sort_field)
+            ])?;
+            for ord in self.model.card_ordinals(note) {
+                insert_card.execute(params![card_id, id, self.id, ord, now, i as i64])?;
+                card_id += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn conf_json(&self) -> serde_json::Value {
+        json!({
+            "activeDecks": [self.id],
+            "curDeck": self.id,
+            "newSpread": 0,
+            "collapseTime": 1200,
+            "timeLim": 0,
+            "estTimes": true,
+            "dueCounts": true,
+            "curModel": self.model.id.to_string(),
+            "nextPos": self.notes.len() + 1,
+            "sortType": "noteFld",
+            "sortBackwards": false,
+            "addToCur": true
+        })
+    }
+
+    fn models_json(&self, now: i64) -> serde_json::Value {
+        json!({
+            self.model.id.to_string(): self.model.to_json(self.id, now)
+        })
+    }
+
+    fn decks_json(&self, now: i64) -> serde_json::Value {
+        json!({
+            "1": deck_json(1, "Default", "", now),
+            self.id.to_string(): deck_json(self.id, &self.name, &self.description, now)
+        })
+    }
+}
+
+fn deck_json(id: i64, name: &str, description: &str, now: i64) -> serde_json::Value {
+    json!({
+        "id": id,
+        "name": name,
+        "desc": description,
+        "mod": now,
+        "usn": -1,
+        "conf": 1,
+        "dyn": 0,
+        "collapsed": false,
+        "browserCollapsed": false,
+        "extendNew": 10,
+        "extendRev": 50,
+        "newToday": [0, 0],
+        "revToday": [0, 0],
+        "lrnToday": [0, 0],
+        "timeToday": [0, 0]
+    })
+}
+
+fn dconf_json() -> serde_json::Value {
+    json!({
+        "1": {
+            "id": 1,
+            "name": "Default",
+            "mod": 0,
+            "usn": 0,
+            "maxTaken": 60,
+            "autoplay": true,
+            "timer": 0,
+            "replayq": true,
+            "dyn": false,
+            "new": {
+                "delays": [1, 10],
+                "ints": [1, 4, 7],
+                "initialFactor": 2500,
+                "separate": true,
+                "order": 1,
+                "perDay": 20,
+                "bury": true
+            },
+            "lapse": {
+                "delays": [10],
+                "mult": 0,
+                "minInt": 1,
+                "leechFails": 8,
+                "leechAction": 0
+            },
+            "rev": {
+                "perDay": 200,
+                "ease4": 1.3,
+                "fuzz": 0.05,
+                "minSpace": 1,
+                "ivlFct": 1,
+                "maxIvl": 36500,
+                "bury": true
+            }
+        }
+    })
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before 1970")
+        .as_millis() as i64
+}
+
+// Anki detects duplicate notes by the first 8 hex digits of the SHA1 of the sort field
+fn field_checksum(field: &str) -> i64 {
+    let hash = Sha1::digest(strip_html(field).as_bytes());
+    u32::from_be_bytes(hash[..4].try_into().unwrap()) as i64
+}
+
+pub(crate) fn strip_html(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => result.push(c),
+            _ => ()
+        }
+    }
+    result
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+    use jp2anki::test_util::entry;
+    use jp2anki_dict::{DictionaryEntry, Definition, Source};
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::guid::note_guid;
+
+    fn result(word: &str, reading: &str, definition: &str) -> AnalyzerResult {
+        AnalyzerResult {
+            dict_info: vec![DictionaryEntry {
+                definitions: vec![
+                    Definition::new(definition.into(), vec!["名詞".into()], vec![]).unwrap()
+                ],
+                ..entry(Source::JMDict(1), &[word], &[reading])
+            }],
+            ..jp2anki::test_util::result(word, reading)
+        }
+    }
+
+    #[test]
+    fn test_note_from_result() {
+        let note = NoteModel::default().note(&result("猫", "ねこ", "cat <feline>"));
+        assert_eq!(note.fields[0], "猫");
+        assert_eq!(note.fields[1], "ねこ");
+        assert_eq!(note.fields[2], "<ol><li>cat &lt;feline&gt;</li></ol>");
+        assert_eq!(note.guid, note_guid(Some(Source::JMDict(1)), "猫", "ねこ"));
+    }
+
+    #[test]
+    fn test_write_apkg() {
+        let mut deck = Deck::new("jp2anki test");
+        deck.add_results(&[result("猫", "ねこ", "cat"), result("犬", "いぬ", "dog")]);
+        deck.add_media("neko.mp3", vec![1, 2, 3]);
+
+        let mut apkg = Cursor::new(Vec::new());
+        deck.write_apkg(&mut apkg).expect("error in deck.write_apkg");
+
+        let mut zip = ZipArchive::new(apkg).expect("apkg is not a zip file");
+        let mut media = String::new();
+        zip.by_name("media").unwrap().read_to_string(&mut media).unwrap();
+        assert_eq!(media, r#"{"0":"neko.mp3"}"#);
+
+        let mut collection = Vec::new();
+        zip.by_name("collection.anki2").unwrap().read_to_end(&mut collection).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("collection.anki2");
+        fs::write(&path, collection).unwrap();
+
+        let conn = Connection::open(&path).unwrap();
+        let notes: i64 = conn.query_row("SELECT count(*) FROM notes", [], |row| row.get(0)).unwrap();
+        let cards: i64 = conn.query_row("SELECT count(*) FROM cards", [], |row| row.get(0)).unwrap();
+        assert_eq!(notes, 2);
+        // Recognition, reading and production card for each word
+        assert_eq!(cards, 6);
+
+        let sfld: String = conn.query_row("SELECT sfld FROM notes ORDER BY id LIMIT 1", [], |row| row.get(0)).unwrap();
+        assert_eq!(sfld, "猫");
+    }
+}
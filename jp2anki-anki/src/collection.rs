@@ -0,0 +1,324 @@
+use std::{cmp::Ordering, collections::HashSet, fs::File, io, path::Path};
+use jp2anki::AnalyzerResult;
+use jp2anki_dict::normalize::normalize;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params};
+use ruzstd::decoding::StreamingDecoder;
+use serde::{Serialize, Deserialize};
+use zip::ZipArchive;
+
+use crate::{AnkiError, Result, apkg::strip_html};
+
+// Newer Anki versions put several collections into a package, of which the
+// .anki2 one only has a note asking to update Anki. The .anki21b one is
+// compressed with zstd.
+const COLLECTION_NAMES: [&str; 3] = ["collection.anki21b", "collection.anki21", "collection.anki2"];
+
+/// Which notes of an Anki collection count as known words
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CollectionImport {
+    pub note_type: String,
+    pub field: String,
+    /// Only keep notes with a card whose interval is at least this many days
+    pub min_interval: Option<i64>
+}
+
+impl CollectionImport {
+    pub fn new(note_type: &str, field: &str) -> Self {
+        CollectionImport {
+            note_type: note_type.to_owned(),
+            field: field.to_owned(),
+            min_interval: None
+        }
+    }
+
+    /// Reads the known words from an `.apkg`/`.colpkg` package or an `.anki2` collection
+    pub fn read(&self, path: impl AsRef<Path>) -> Result<HashSet<String>> {
+        let path = path.as_ref();
+        let is_package = path.extension()
+            .is_some_and(|ext| ext == "apkg" || ext == "colpkg");
+
+        if is_package {
+            let dir = tempfile::tempdir()?;
+            let collection_path = dir.path().join("collection.anki2");
+
+            let mut zip = ZipArchive::new(File::open(path)?)?;
+            let name = COLLECTION_NAMES.iter()
+                .find(|name| zip.by_name(name).is_ok())
+                .ok_or(AnkiError::MissingCollection)?;
+            let mut collection = zip.by_name(name)?;
+            let mut collection_file = File::create(&collection_path)?;
+            if name.ends_with(".anki21b") {
+                io::copy(&mut StreamingDecoder::new(collection)?, &mut collection_file)?;
+            } else {
+                io::copy(&mut collection, &mut collection_file)?;
+            }
+
+            let conn = Connection::open_with_flags(&collection_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+            self.read_collection(&conn)
+        } else {
+            let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+            self.read_collection(&conn)
+        }
+    }
+
+    pub fn read_collection(&self, conn: &Connection) -> Result<HashSet<String>> {
+        let (model_id, field_ord) = self.find_field(conn)?;
+
+        let mut query = conn.prepare(
+            "SELECT notes.flds, max(cards.ivl) FROM notes
+                JOIN cards ON cards.nid = notes.id
+                WHERE notes.mid = ?
+                GROUP BY notes.id"
+        )?;
+        let mut rows = query.query(params![model_id])?;
+
+        let mut words = HashSet::new();
+        while let Some(row) = rows.next()? {
+            let fields: String = row.get(0)?;
+            let interval: i64 = row.get(1)?;
+            if self.min_interval.is_some_and(|min| interval < min) {
+                continue;
+            }
+
+            if let Some(field) = fields.split('\x1f').nth(field_ord) {
+                let word = clean_field(field);
+                if !word.is_empty() {
+                    words.insert(word);
+                }
+            }
+        }
+
+        Ok(words)
+    }
+
+    /// Finds the note type id and field ordinal, both in the current schema
+    /// with separate tables and in the older one with JSON in `col.models`
+    fn find_field(&self, conn: &Connection) -> Result<(i64, usize)> {
+        let has_notetypes: bool = conn.query_row(
+            "SELECT count(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'notetypes'",
+            [],
+            |row| row.get(0)
+        )?;
+
+        let found = if has_notetypes {
+            conn.create_collation("unicase", unicase)?;
+            let model_id: i64 = conn.query_row(
+                "SELECT id FROM notetypes WHERE name = ?",
+                params![self.note_type],
+                |row| row.get(0)
+            ).optional()?.ok_or_else(|| AnkiError::UnknownNoteType(self.note_type.clone()))?;
+
+            conn.query_row(
+                "SELECT ord FROM fields WHERE ntid = ? AND name = ?",
+                params![model_id, self.field],
+                |row| row.get::<_, i64>(0)
+            ).optional()?.map(|ord| (model_id, ord as usize))
+        } else {
+            let models: String = conn.query_row("SELECT models FROM col", [], |row| row.get(0))?;
+            let models: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&models)?;
+            let model = models.values()
+                .find(|model| model["name"] == self.note_type.as_str())
+                .ok_or_else(|| AnkiError::UnknownNoteType(self.note_type.clone()))?;
+
+            model["flds"].as_array()
+                .and_then(|fields| fields.iter().position(|field| field["name"] == self.field.as_str()))
+                .and_then(|ord| Some((model["id"].as_i64()?, ord)))
+        };
+
+        found.ok_or_else(|| AnkiError::UnknownField {
+            note_type: self.note_type.clone(),
+            field: self.field.clone()
+        })
+    }
+}
+
+/// Anki's collation of note type and field names in the current schema,
+/// which SQLite doesn't know of and refuses to compare them without
+fn unicase(a: &str, b: &str) -> Ordering {
+    a.to_lowercase().cmp(&b.to_lowercase())
+}
+
+/// Removes markup and "Japanese Support" furigana (`食[た]べ 物[もの]`) from a field
+fn clean_field(field: &str) -> String {
+    let text = strip_html(field).replace("&nbsp;", " ");
+    let mut word = String::with_capacity(text.len());
+    let mut in_furigana = false;
+    for c in text.chars() {
+        match c {
+            '[' => in_furigana = true,
+            ']' if in_furigana => in_furigana = false,
+            c if in_furigana || c.is_whitespace() => (),
+            c => word.push(c)
+        }
+    }
+    word
+}
+
+/// Drops every result whose word is known, or that is usually written in
+/// kana and has a known word as its reading, like ありがとう for 有難う.
+/// Knowing はし doesn't hide its homophones 橋, 箸 and 端. Words are compared
+/// in their normalized form, so katakana readings match hiragana words.
+pub fn filter_known(results: Vec<AnalyzerResult>, known: &HashSet<String>) -> Vec<AnalyzerResult> {
+    let known: HashSet<String> = known.iter().map(|word| normalize(word)).collect();
+    results.into_iter()
+        .filter(|result| {
+            let reading = normalize(&result.reading);
+            let known_in_kana = known.contains(&reading) && result.dict_info.iter()
+                .any(|entry| entry.usually_kana() && entry.readings.iter().any(|r| normalize(r) == reading));
+            !known.contains(&normalize(&result.word)) && !known_in_kana
+        })
+        .collect()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::{Read, Write}};
+    use jp2anki::test_util::entry;
+    use jp2anki_dict::{Definition, DictionaryEntry, Source};
+
+    use super::*;
+    use crate::Deck;
+
+    fn result(word: &str) -> AnalyzerResult {
+        jp2anki::test_util::result(word, word)
+    }
+
+    #[test]
+    fn test_filter_known() {
+        let known = HashSet::from(["猫", "ありがとう", "上手い", "はし"].map(String::from));
+        let with_entry = |word, reading, forms: &[&str], readings: &[&str], flags: &[&str]| AnalyzerResult {
+            dict_info: vec![DictionaryEntry {
+                definitions: vec![Definition::new(
+                    String::new(),
+                    vec![],
+                    flags.iter().map(|flag| flag.to_string()).collect()
+                ).unwrap()],
+                ..entry(Source::JMDict(1), forms, readings)
+            }],
+            ..jp2anki::test_util::result(word, reading)
+        };
+        let results = vec![
+            jp2anki::test_util::result("猫", "ネコ"),
+            with_entry("有難う", "アリガトウ", &["有難う"], &["ありがとう"], &["&uk;"]),
+            jp2anki::test_util::result("鳥", "トリ"),
+            with_entry("上手", "ジョウズ", &["上手", "上手い"], &["じょうず"], &[]),
+            with_entry("橋", "ハシ", &["橋"], &["はし"], &[]),
+        ];
+
+        let unknown = filter_known(results, &known);
+        let words: Vec<&str> = unknown.iter().map(|result| result.word.as_str()).collect();
+        assert_eq!(words, vec!["鳥", "上手", "橋"]);
+    }
+
+    #[test]
+    fn test_read_anki21b() {
+        let dir = tempfile::tempdir().unwrap();
+        let apkg_path = dir.path().join("known.apkg");
+        let mut deck = Deck::new("known");
+        deck.add_results(&[result("猫")]);
+        deck.write_apkg(File::create(&apkg_path).unwrap()).unwrap();
+
+        // Repackage the collection the way current Anki versions export it
+        let mut collection = Vec::new();
+        let mut zip = ZipArchive::new(File::open(&apkg_path).unwrap()).unwrap();
+        zip.by_name("collection.anki2").unwrap().read_to_end(&mut collection).unwrap();
+        let anki21b_path = dir.path().join("known-anki21b.apkg");
+        let mut writer = zip::ZipWriter::new(File::create(&anki21b_path).unwrap());
+        writer.start_file("collection.anki2", Default::default()).unwrap();
+        writer.write_all(b"Please update to the latest Anki version").unwrap();
+        writer.start_file("collection.anki21b", Default::default()).unwrap();
+        writer.write_all(&ruzstd::encoding::compress_to_vec(&collection[..], ruzstd::encoding::CompressionLevel::Fastest)).unwrap();
+        writer.finish().unwrap();
+
+        let words = CollectionImport::new("jp2anki", "Word").read(&anki21b_path).expect("error reading anki21b");
+        assert_eq!(words, HashSet::from(["猫".to_string()]));
+    }
+
+    #[test]
+    fn test_read_notetypes_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let apkg_path = dir.path().join("known.apkg");
+        let mut deck = Deck::new("known");
+        deck.add_results(&[result("猫")]);
+        deck.write_apkg(File::create(&apkg_path).unwrap()).unwrap();
+
+        // Move the note type into the tables of schema 15 and later
+        let mut collection = Vec::new();
+        let mut zip = ZipArchive::new(File::open(&apkg_path).unwrap()).unwrap();
+        zip.by_name("collection.anki2").unwrap().read_to_end(&mut collection).unwrap();
+        let anki2_path = dir.path().join("collection.anki2");
+        fs::write(&anki2_path, collection).unwrap();
+        let conn = Connection::open(&anki2_path).unwrap();
+        conn.create_collation("unicase", unicase).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE notetypes (
+                id integer NOT NULL PRIMARY KEY,
+                name text NOT NULL COLLATE unicase,
+                mtime_secs integer NOT NULL,
+                usn integer NOT NULL,
+                config blob NOT NULL
+            );
+            CREATE UNIQUE INDEX idx_notetypes_name ON notetypes (name);
+            CREATE TABLE fields (
+                ntid integer NOT NULL,
+                ord integer NOT NULL,
+                name text NOT NULL COLLATE unicase,
+                config blob NOT NULL,
+                PRIMARY KEY (ntid, ord)
+            ) WITHOUT ROWID;
+            CREATE UNIQUE INDEX idx_fields_name_ntid ON fields (name, ntid);
+            INSERT INTO notetypes SELECT mid, 'jp2anki', 0, 0, x'' FROM notes LIMIT 1;
+            INSERT INTO fields SELECT id, 0, 'Word', x'' FROM notetypes;
+            UPDATE col SET models = '{}';"
+        ).unwrap();
+        drop(conn);
+
+        // Names are compared ignoring case, like Anki does
+        let words = CollectionImport::new("JP2ANKI", "word").read(&anki2_path).expect("error reading anki2");
+        assert_eq!(words, HashSet::from(["猫".to_string()]));
+    }
+
+    #[test]
+    fn test_clean_field() {
+        assert_eq!(clean_field("<b>食[た]べ 物[もの]</b>&nbsp;"), "食べ物");
+    }
+
+    #[test]
+    fn test_read_known_words() {
+        let dir = tempfile::tempdir().unwrap();
+        let apkg_path = dir.path().join("known.apkg");
+
+        let mut deck = Deck::new("known");
+        deck.add_results(&[result("猫"), result("犬")]);
+        deck.write_apkg(File::create(&apkg_path).unwrap()).unwrap();
+
+        let import = CollectionImport::new("jp2anki", "Word");
+        let words = import.read(&apkg_path).expect("error reading apkg");
+        assert_eq!(words, HashSet::from(["猫".to_string(), "犬".to_string()]));
+
+        // Make only the cards of the first note mature
+        let mut collection = Vec::new();
+        let mut zip = ZipArchive::new(File::open(&apkg_path).unwrap()).unwrap();
+        zip.by_name("collection.anki2").unwrap().read_to_end(&mut collection).unwrap();
+        let anki2_path = dir.path().join("collection.anki2");
+        fs::write(&anki2_path, collection).unwrap();
+        Connection::open(&anki2_path).unwrap()
+            .execute("UPDATE cards SET ivl = 30 WHERE nid = (SELECT min(id) FROM notes)", [])
+            .unwrap();
+
+        let import = CollectionImport { min_interval: Some(21), ..import };
+        let words = import.read(&anki2_path).expect("error reading anki2");
+        assert_eq!(words, HashSet::from(["猫".to_string()]));
+
+        let unknown = filter_known(vec![result("猫"), result("鳥")], &words);
+        assert_eq!(unknown.len(), 1);
+        assert_eq!(unknown[0].word, "鳥");
+
+        match CollectionImport::new("jp2anki", "Expression").read(&anki2_path) {
+            Err(AnkiError::UnknownField { .. }) => (),
+            other => panic!("unexpected result: {:?}", other)
+        }
+    }
+}
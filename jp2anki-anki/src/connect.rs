@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use jp2anki::AnalyzerResult;
+use reqwest::blocking::Client;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde_json::json;
+
+use crate::{AnkiError, Result, model::NoteModel};
+
+const ANKI_CONNECT_URL: &str = "http://127.0.0.1:8765";
+const ANKI_CONNECT_VERSION: u32 = 6;
+
+#[derive(Serialize)]
+struct Request<'a, P: Serialize> {
+    action: &'a str,
+    version: u32,
+    params: P
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+    result: Option<T>,
+    error: Option<String>
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectNote {
+    pub deck_name: String,
+    pub model_name: String,
+    pub fields: HashMap<String, String>,
+    pub tags: Vec<String>,
+    pub options: ConnectNoteOptions
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectNoteOptions {
+    pub allow_duplicate: bool,
+    pub duplicate_scope: String
+}
+
+impl Default for ConnectNoteOptions {
+    fn default() -> Self {
+        ConnectNoteOptions {
+            allow_duplicate: false,
+            duplicate_scope: "deck".into()
+        }
+    }
+}
+
+/// Outcome of pushing analyzed words into Anki, by word. Words are listed
+/// once for each of their notes, which cloze note types have per sentence.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct PushReport {
+    pub added: Vec<String>,
+    pub existing: Vec<String>,
+    pub failed: Vec<String>
+}
+
+/// Client for the AnkiConnect add-on's JSON-RPC interface
+pub struct AnkiConnect {
+    client: Client,
+    url: String
+}
+
+impl AnkiConnect {
+    pub fn new() -> Self {
+        AnkiConnect::with_url(ANKI_CONNECT_URL)
+    }
+
+    pub fn with_url(url: &str) -> Self {
+        AnkiConnect { client: Client::new(), url: url.to_owned() }
+    }
+
+    fn invoke<P: Serialize, T: DeserializeOwned>(&self, action: &str, params: P) -> Result<T> {
+        let request = Request { action, version: ANKI_CONNECT_VERSION, params };
+        let response: Response<T> = self.client.post(&self.url)
+            .json(&request)
+            .send()?
+            .error_for_status()?
+            .json()?;
+
+        match response {
+            Response { error: Some(error), .. } => Err(AnkiError::AnkiConnect(error)),
+            Response { result: Some(result), .. } => Ok(result),
+            // Actions like createModel may legitimately return null
+            Response { result: None, .. } => Ok(serde_json::from_value(serde_json::Value::Null)?)
+        }
+    }
+
+    pub fn deck_names(&self) -> Result<Vec<String>> {
+        self.invoke("deckNames", json!({}))
+    }
+
+    pub fn create_deck(&self, deck: &str) -> Result<i64> {
+        self.invoke("createDeck", json!({ "deck": deck }))
+    }
+
+    pub fn model_names(&self) -> Result<Vec<String>> {
+        self.invoke("modelNames", json!({}))
+    }
+
+    pub fn create_model(&self, model: &NoteModel) -> Result<()> {
+        let templates: Vec<_> = model.templates.iter()
+            .map(|template| json!({
+                "Name": template.name,
+                "Front": template.front,
+                "Back": template.back
+            }))
+            .collect();
+        let fields: Vec<&str> = model.fields.iter()
+            .map(|field| field.name.as_str())
+            .collect();
+
+        let _: serde_json::Value = self.invoke("createModel", json!({
+            "modelName": model.name,
+            "inOrderFields": fields,
+            "css": model.css,
+            "isCloze": model.cloze,
+            "cardTemplates": templates
+        }))?;
+        Ok(())
+    }
+
+    pub fn find_notes(&self, query: &str) -> Result<Vec<i64>> {
+        self.invoke("findNotes", json!({ "query": query }))
+    }
+
+    pub fn can_add_notes(&self, notes: &[ConnectNote]) -> Result<Vec<bool>> {
+        self.invoke("canAddNotes", json!({ "notes": notes }))
+    }
+
+    pub fn add_notes(&self, notes: &[ConnectNote]) -> Result<Vec<Option<i64>>> {
+        self.invoke("addNotes", json!({ "notes": notes }))
+    }
+
+    pub fn store_media_file(&self, filename: &str, data: &[u8]) -> Result<String> {
+        self.invoke("storeMediaFile", json!({
+            "filename": filename,
+            "data": base64::encode(data)
+        }))
+    }
+
+    /// The notes for a word, like the ones `Deck` exports: a single one, or
+    /// one per sentence for cloze note types
+    pub fn notes(&self, deck: &str, model: &NoteModel, result: &AnalyzerResult) -> Vec<ConnectNote> {
+        model.notes_with_audio(result, None).into_iter()
+            .map(|note| ConnectNote {
+                deck_name: deck.to_owned(),
+                model_name: model.name.clone(),
+                fields: model.fields.iter()
+                    .map(|field| field.name.clone())
+                    .zip(note.fields)
+                    .collect(),
+                tags: note.tags,
+                options: Default::default()
+            })
+            .collect()
+    }
+
+    /// Adds a note for every word that isn't in the deck yet, creating the deck
+    /// and note type first if necessary. Words that can't be added are only
+    /// reported as existing if the deck has a note for them.
+    pub fn push_results(&self, deck: &str, model: &NoteModel, results: &[AnalyzerResult]) -> Result<PushReport> {
+        if !self.deck_names()?.iter().any(|name| name == deck) {
+            self.create_deck(deck)?;
+        }
+        if !self.model_names()?.contains(&model.name) {
+            self.create_model(model)?;
+        }
+
+        let (words, notes): (Vec<&str>, Vec<ConnectNote>) = results.iter()
+            .flat_map(|result| {
+                self.notes(deck, model, result).into_iter().map(|note| (result.word.as_str(), note))
+            })
+            .unzip();
+        let can_add = self.can_add_notes(&notes)?;
+
+        let mut report = PushReport::default();
+        let mut new_words = Vec::new();
+        let mut new_notes = Vec::new();
+        for ((word, note), can_add) in words.into_iter().zip(notes).zip(can_add) {
+            if can_add {
+                new_words.push(word.to_owned());
+                new_notes.push(note);
+            } else if self.is_duplicate(model, &note)? {
+                report.existing.push(word.to_owned());
+            } else {
+                report.failed.push(word.to_owned());
+            }
+        }
+
+        if !new_notes.is_empty() {
+            for (word, id) in new_words.into_iter().zip(self.add_notes(&new_notes)?) {
+                match id {
+                    Some(_) => report.added.push(word),
+                    None => report.failed.push(word)
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    // canAddNotes also refuses notes with an empty first field or other
+    // problems, so only notes that findNotes can find count as existing
+    fn is_duplicate(&self, model: &NoteModel, note: &ConnectNote) -> Result<bool> {
+        let Some(first_field) = model.fields.first() else { return Ok(false) };
+        let value = note.fields.get(&first_field.name).map_or("", String::as_str);
+        if value.is_empty() {
+            return Ok(false);
+        }
+
+        let query = format!(
+            "\"deck:{}\" \"note:{}\" \"{}:{}\"",
+            escape_search(&note.deck_name),
+            escape_search(&note.model_name),
+            escape_search(&first_field.name),
+            escape_search(value)
+        );
+        Ok(!self.find_notes(&query)?.is_empty())
+    }
+}
+
+// Makes text match literally inside a quoted Anki search term
+fn escape_search(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '"' | '*' | '_') {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+impl Default for AnkiConnect {
+    fn default() -> Self {
+        AnkiConnect::new()
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::{io::{BufRead, BufReader, Read, Write}, net::TcpListener, sync::{Arc, Mutex}, thread};
+    use jp2anki::test_util::occurrence;
+    use serde_json::Value;
+
+    use super::*;
+
+    // Minimal HTTP server that answers every request with `handler(request body)`
+    fn mock_server(handler: fn(&Value) -> Value) -> (String, Arc<Mutex<Vec<Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let log = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let log = log.clone();
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    loop {
+                        let mut content_length = 0;
+                        let mut line = String::new();
+                        loop {
+                            line.clear();
+                            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                                return;
+                            }
+                            let header = line.trim_end().to_ascii_lowercase();
+                            if header.is_empty() {
+                                break;
+                            }
+                            if let Some(len) = header.strip_prefix("content-length:") {
+                                content_length = len.trim().parse().unwrap();
+                            }
+                        }
+
+                        let mut body = vec![0u8; content_length];
+                        reader.read_exact(&mut body).unwrap();
+                        let request: Value = serde_json::from_slice(&body).unwrap();
+                        let response = handler(&request).to_string();
+                        log.lock().unwrap().push(request);
+
+                        write!(
+                            stream,
+                            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                            response.len(), response
+                        ).unwrap();
+                    }
+                });
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn result(word: &str) -> AnalyzerResult {
+        jp2anki::test_util::result(word, word)
+    }
+
+    #[test]
+    fn test_error_response() {
+        let (url, _) = mock_server(|_| json!({ "result": null, "error": "collection is not available" }));
+        let client = AnkiConnect::with_url(&url);
+        match client.find_notes("deck:current") {
+            Err(AnkiError::AnkiConnect(error)) => assert_eq!(error, "collection is not available"),
+            other => panic!("unexpected result: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_push_results() {
+        let (url, requests) = mock_server(|request| {
+            let result = match request["action"].as_str().unwrap() {
+                "deckNames" => json!(["Default", "Mining"]),
+                "modelNames" => json!(["Basic"]),
+                "createModel" => json!({}),
+                // The first word is already in the deck, the third is refused for another reason
+                "canAddNotes" => json!([false, true, false, true]),
+                "findNotes" => match request["params"]["query"].as_str().unwrap().contains("猫") {
+                    true => json!([1660000000000i64]),
+                    false => json!([])
+                },
+                "addNotes" => json!([1660000000001i64, null]),
+                _ => Value::Null
+            };
+            json!({ "result": result, "error": null })
+        });
+
+        let client = AnkiConnect::with_url(&url);
+        let report = client.push_results(
+            "Mining",
+            &NoteModel::default(),
+            &[result("猫"), result("犬"), result("鳥"), result("魚")]
+        ).expect("error in push_results");
+
+        assert_eq!(report.existing, vec!["猫"]);
+        assert_eq!(report.added, vec!["犬"]);
+        assert_eq!(report.failed, vec!["鳥", "魚"]);
+
+        let requests = requests.lock().unwrap();
+        let actions: Vec<&str> = requests.iter()
+            .map(|request| request["action"].as_str().unwrap())
+            .collect();
+        assert_eq!(actions, vec!["deckNames", "modelNames", "createModel", "canAddNotes", "findNotes", "findNotes", "addNotes"]);
+        assert_eq!(requests[4]["params"]["query"], "\"deck:Mining\" \"note:jp2anki\" \"Word:猫\"");
+
+        let added = &requests[6]["params"]["notes"];
+        assert_eq!(added.as_array().unwrap().len(), 2);
+        assert_eq!(added[0]["deckName"], "Mining");
+        assert_eq!(added[0]["fields"]["Word"], "犬");
+        assert_eq!(requests[0]["version"], 6);
+        assert_eq!(requests[2]["params"]["isCloze"], false);
+    }
+
+    #[test]
+    fn test_push_cloze_results() {
+        let (url, requests) = mock_server(|request| {
+            let result = match request["action"].as_str().unwrap() {
+                "deckNames" => json!(["Mining"]),
+                "modelNames" => json!([]),
+                "canAddNotes" => json!([true, true]),
+                "addNotes" => json!([1660000000000i64, 1660000000001i64]),
+                _ => Value::Null
+            };
+            json!({ "result": result, "error": null })
+        });
+
+        let neko = AnalyzerResult {
+            count: 2,
+            sentences: vec!["猫がいる。".into(), "黒い猫だ。".into()],
+            occurrences: vec![occurrence("猫", 0, 0, 0), occurrence("猫", 1, "猫がいる。".len(), "黒い".len())],
+            ..result("猫")
+        };
+        let client = AnkiConnect::with_url(&url);
+        let report = client.push_results("Mining", &NoteModel::default_cloze(), &[neko])
+            .expect("error in push_results");
+        assert_eq!(report.added, vec!["猫", "猫"]);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[2]["action"], "createModel");
+        assert_eq!(requests[2]["params"]["isCloze"], true);
+        let added = &requests[4]["params"]["notes"];
+        assert_eq!(added[0]["fields"]["Text"], "{{c1::猫}}がいる。");
+        assert_eq!(added[1]["fields"]["Text"], "黒い{{c1::猫}}だ。");
+    }
+
+    #[test]
+    fn test_escape_search() {
+        assert_eq!(escape_search(r#"say "hi"_*\"#), r#"say \"hi\"\_\*\\"#);
+    }
+}
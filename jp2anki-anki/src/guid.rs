@@ -0,0 +1,122 @@
+use jp2anki::AnalyzerResult;
+use jp2anki_dict::{DictionaryEntry, Source, normalize::normalize};
+use sha1::{Sha1, Digest};
+
+// Alphabet used by Anki's base91 note guids
+const BASE91_TABLE: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ\
+    0123456789!#$%&()*+,-./:;<=>?@[]^_`{|}~";
+
+/// Deterministic note guid for a dictionary entry, form and reading.
+///
+/// Anki updates a note instead of adding a duplicate when an imported note has
+/// the same guid, so the same word always has to hash to the same value.
+pub fn note_guid(source: Option<Source>, form: &str, reading: &str) -> String {
+    let source = match source {
+        Some(Source::WaniKani(id)) => format!("wanikani:{}", id),
+        Some(Source::JMDict(ent_seq)) => format!("jmdict:{}", ent_seq),
+        None => String::from("none")
+    };
+    let key = format!("{}\x1f{}\x1f{}", source, form, reading);
+
+    let hash = Sha1::digest(key.as_bytes());
+    base91(u64::from_be_bytes(hash[..8].try_into().unwrap()))
+}
+
+/// Guid of the note generated for an analyzed word.
+///
+/// `lookup` returns entries in no particular order, so the entry the guid is
+/// derived from is chosen by its `Source` rather than by position. The
+/// reading is always the analyzed one, so homographs like 今日 (きょう) and
+/// 今日 (こんにち) get separate notes even when they share an entry.
+pub fn result_guid(result: &AnalyzerResult) -> String {
+    let reading = normalize(&result.reading);
+    let entry = result.dict_info.iter()
+        .filter(|entry| matches_word(entry, &result.word))
+        .min_by_key(|entry| (!has_reading(entry, &reading), entry.source))
+        .or_else(|| result.dict_info.iter().min_by_key(|entry| (!has_reading(entry, &reading), entry.source)));
+
+    match entry {
+        Some(entry) => {
+            let form = if entry.forms.contains(&result.word) {
+                result.word.as_str()
+            } else {
+                entry.forms.first().unwrap_or(&result.word)
+            };
+            let entry_reading = entry.readings.iter()
+                .find(|entry_reading| normalize(entry_reading) == reading)
+                .unwrap_or(&reading);
+            note_guid(Some(entry.source), form, entry_reading)
+        },
+        None => note_guid(None, &result.word, &reading)
+    }
+}
+
+/// Guid of a sentence note, such as a cloze, for an analyzed word
+pub fn sentence_guid(result: &AnalyzerResult, sentence: &str) -> String {
+    let key = format!("{}\x1f{}", result_guid(result), sentence);
+    let hash = Sha1::digest(key.as_bytes());
+    base91(u64::from_be_bytes(hash[..8].try_into().unwrap()))
+}
+
+fn has_reading(entry: &DictionaryEntry, reading: &str) -> bool {
+    entry.readings.iter().any(|entry_reading| normalize(entry_reading) == reading)
+}
+
+fn matches_word(entry: &DictionaryEntry, word: &str) -> bool {
+    entry.forms.iter().chain(entry.readings.iter()).any(|w| w == word)
+}
+
+fn base91(mut value: u64) -> String {
+    let mut guid = Vec::new();
+    while value > 0 {
+        guid.push(BASE91_TABLE[(value % BASE91_TABLE.len() as u64) as usize]);
+        value /= BASE91_TABLE.len() as u64;
+    }
+    guid.reverse();
+    String::from_utf8(guid).unwrap()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use jp2anki::test_util::{entry, result};
+
+    use super::*;
+
+    #[test]
+    fn test_note_guid() {
+        let guid = note_guid(Some(Source::JMDict(1358280)), "食べ物", "たべもの");
+        assert_eq!(guid, note_guid(Some(Source::JMDict(1358280)), "食べ物", "たべもの"));
+        assert_ne!(guid, note_guid(Some(Source::WaniKani(1358280)), "食べ物", "たべもの"));
+        assert_ne!(guid, note_guid(Some(Source::JMDict(1358280)), "食物", "たべもの"));
+        assert!(guid.bytes().all(|b| BASE91_TABLE.contains(&b)));
+    }
+
+    #[test]
+    fn test_result_guid_ignores_entry_order() {
+        let mut kyou = AnalyzerResult {
+            dict_info: vec![
+                entry(Source::JMDict(1579470), &["今日"], &["きょう"]),
+                entry(Source::WaniKani(2667), &["今日"], &["きょう"])
+            ],
+            ..result("今日", "キョウ")
+        };
+        let guid = result_guid(&kyou);
+        kyou.dict_info.reverse();
+        assert_eq!(guid, result_guid(&kyou));
+        assert_eq!(guid, note_guid(Some(Source::WaniKani(2667)), "今日", "きょう"));
+    }
+
+    #[test]
+    fn test_result_guid_homographs() {
+        let homograph = |reading: &str| AnalyzerResult {
+            dict_info: vec![entry(Source::JMDict(1579470), &["今日"], &["きょう", "こんにち"])],
+            ..result("今日", reading)
+        };
+        let kyou = result_guid(&homograph("キョウ"));
+        let konnichi = result_guid(&homograph("コンニチ"));
+        assert_ne!(kyou, konnichi);
+        assert_eq!(konnichi, note_guid(Some(Source::JMDict(1579470)), "今日", "こんにち"));
+    }
+}
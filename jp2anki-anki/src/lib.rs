@@ -0,0 +1,42 @@
+pub mod apkg;
+pub mod collection;
+pub mod connect;
+pub mod guid;
+pub mod model;
+
+pub use apkg::{Deck, Note};
+pub use collection::{CollectionImport, filter_known};
+pub use connect::{AnkiConnect, PushReport};
+pub use model::{NoteModel, FieldSource};
+pub use jp2anki_audio::AudioCache;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum AnkiError {
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("Zip error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("Zstd error: {0}")]
+    Zstd(#[from] ruzstd::decoding::errors::FrameDecoderError),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("AnkiConnect error: {0}")]
+    AnkiConnect(String),
+    #[error("No collection found in package")]
+    MissingCollection,
+    #[error("Unknown note type: {0:?}")]
+    UnknownNoteType(String),
+    #[error("Note type {note_type:?} has no field {field:?}")]
+    UnknownField {
+        note_type: String,
+        field: String
+    },
+    #[error("IO error: {0}")]
+    IO(#[from] std::io::Error)
+}
+
+pub type Result<T, E=AnkiError> = std::result::Result<T, E>;
@@ -0,0 +1,480 @@
+use std::collections::BTreeSet;
+use jp2anki::{AnalyzerResult, furigana::{self, escape_html}, cloze::{ClozeSentence, cloze_sentences}};
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use sha1::{Sha1, Digest};
+
+use crate::{AudioCache, apkg::Note, guid::{result_guid, sentence_guid}};
+
+const DEFAULT_CSS: &str = ".card { font-family: sans-serif; font-size: 20px; text-align: center; }\n\
+    .word { font-size: 48px; }\n\
+    .reading { font-size: 28px; }\n\
+    .examples { font-size: 16px; text-align: left; }";
+
+const LATEX_PRE: &str = "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\
+    \\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\begin{document}\n";
+const LATEX_POST: &str = "\\end{document}";
+
+/// The piece of analyzer or dictionary data that fills a note field
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldSource {
+    Word,
+    /// The word with furigana in bracket syntax, for `{{furigana:...}}`
+    WordFurigana,
+    Forms,
+    Reading,
+    PartOfSpeech,
+    Count,
+    Definitions,
+    Examples,
+    Audio,
+    /// A sentence the word occurs in
+    Sentence,
+    /// The same sentence with the word as a cloze deletion
+    Cloze,
+    Empty
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelField {
+    pub name: String,
+    pub source: FieldSource
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CardTemplate {
+    pub name: String,
+    pub front: String,
+    pub back: String
+}
+
+/// An Anki note type: its fields, the card templates generated from them and the shared CSS
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NoteModel {
+    pub id: i64,
+    pub name: String,
+    pub fields: Vec<ModelField>,
+    pub templates: Vec<CardTemplate>,
+    pub css: String,
+    /// Cloze note types get one note per sentence instead of one per word
+    #[serde(default)]
+    pub cloze: bool
+}
+
+impl NoteModel {
+    pub fn new(name: &str) -> Self {
+        // Derive the id from the name so that re-exports reuse the same note type
+        let hash = Sha1::digest(name.as_bytes());
+        let id = u64::from_be_bytes(hash[..8].try_into().unwrap()) >> 12;
+        NoteModel {
+            id: id as i64,
+            name: name.to_owned(),
+            fields: Vec::new(),
+            templates: Vec::new(),
+            css: DEFAULT_CSS.to_owned(),
+            cloze: false
+        }
+    }
+
+    /// Sentence mining note type: the words are blanked out of the sentences
+    /// they were found in, with the definition as the hint
+    pub fn default_cloze() -> Self {
+        let mut model = NoteModel::new("jp2anki Cloze");
+        model.cloze = true;
+        model.add_field("Text", FieldSource::Cloze);
+        model.add_field("Word", FieldSource::Word);
+        model.add_field("Reading", FieldSource::Reading);
+        model.add_field("Meaning", FieldSource::Definitions);
+        model.add_field("Audio", FieldSource::Audio);
+
+        model.add_template(
+            "Cloze",
+            "{{cloze:Text}}",
+            "{{cloze:Text}}<hr id=\"answer\">\
+                <div class=\"word\">{{Word}}</div>\
+                <div class=\"reading\">{{Reading}}</div>{{Audio}}\
+                <div class=\"meaning\">{{Meaning}}</div>"
+        );
+        model
+    }
+
+    pub fn add_field(&mut self, name: &str, source: FieldSource) {
+        self.fields.push(ModelField { name: name.to_owned(), source });
+    }
+
+    pub fn add_template(&mut self, name: &str, front: &str, back: &str) {
+        self.templates.push(CardTemplate {
+            name: name.to_owned(),
+            front: front.to_owned(),
+            back: back.to_owned()
+        });
+    }
+
+    pub fn note(&self, result: &AnalyzerResult) -> Note {
+        self.note_with_audio(result, None)
+    }
+
+    /// Like `note`, but audio fields play the files from the cache instead of linking to URLs
+    pub fn note_with_audio(&self, result: &AnalyzerResult, audio: Option<&AudioCache>) -> Note {
+        let sentences = cloze_sentences(result);
+        self.render(result, sentences.first(), audio)
+    }
+
+    /// The notes for a word: a single one, or one per sentence for cloze note types
+    pub fn notes_with_audio(&self, result: &AnalyzerResult, audio: Option<&AudioCache>) -> Vec<Note> {
+        if self.cloze {
+            cloze_sentences(result).iter()
+                .map(|sentence| self.render(result, Some(sentence), audio))
+                .collect()
+        } else {
+            vec![self.note_with_audio(result, audio)]
+        }
+    }
+
+    fn render(&self, result: &AnalyzerResult, sentence: Option<&ClozeSentence>, audio: Option<&AudioCache>) -> Note {
+        let media = audio.map(|cache| cached_audio(result, cache))
+            .unwrap_or_default();
+
+        let guid = match sentence {
+            Some(sentence) if self.cloze => sentence_guid(result, &sentence.sentence),
+            _ => result_guid(result)
+        };
+
+        Note {
+            guid,
+            fields: self.fields.iter()
+                .map(|field| match (field.source, audio, sentence) {
+                    (FieldSource::Audio, Some(_), _) => media.iter()
+                        .map(|filename| format!("[sound:{}]", filename))
+                        .collect(),
+                    (FieldSource::Sentence, _, Some(sentence)) => escape_html(&sentence.sentence),
+                    (FieldSource::Cloze, _, Some(sentence)) => escape_html(&sentence.text),
+                    (source, _, _) => render_field(source, result)
+                })
+                .collect(),
+            tags: vec!["jp2anki".into()],
+            media
+        }
+    }
+
+    /// Indices of the fields that the front of a template refers to. Anki only
+    /// generates a card if at least one of them is non-empty.
+    pub fn required_fields(&self, template: &CardTemplate) -> Vec<usize> {
+        let referenced = template_references(&template.front);
+        self.fields.iter()
+            .enumerate()
+            .filter(|(_, field)| referenced.contains(field.name.as_str()))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Ordinals of the templates that produce a card for the given note
+    pub fn card_ordinals(&self, note: &Note) -> Vec<usize> {
+        if self.cloze {
+            return cloze_ordinals(note);
+        }
+
+        self.templates.iter()
+            .enumerate()
+            .filter(|(_, template)| {
+                self.required_fields(template).into_iter()
+                    .any(|i| note.fields.get(i).is_some_and(|field| !field.trim().is_empty()))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    pub fn to_json(&self, deck_id: i64, now: i64) -> serde_json::Value {
+        let fields: Vec<_> = self.fields.iter()
+            .enumerate()
+            .map(|(i, field)| json!({
+                "name": field.name,
+                "ord": i,
+                "sticky": false,
+                "rtl": false,
+                "font": "Arial",
+                "size": 20,
+                "media": []
+            }))
+            .collect();
+
+        let templates: Vec<_> = self.templates.iter()
+            .enumerate()
+            .map(|(i, template)| json!({
+                "name": template.name,
+                "ord": i,
+                "qfmt": template.front,
+                "afmt": template.back,
+                "did": null,
+                "bqfmt": "",
+                "bafmt": ""
+            }))
+            .collect();
+
+        let req: Vec<_> = self.templates.iter()
+            .enumerate()
+            .map(|(i, template)| json!([i, "any", self.required_fields(template)]))
+            .collect();
+
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "type": if self.cloze { 1 } else { 0 },
+            "mod": now,
+            "usn": -1,
+            "sortf": 0,
+            "did": deck_id,
+            "tmpls": templates,
+            "flds": fields,
+            "css": self.css,
+            "latexPre": LATEX_PRE,
+            "latexPost": LATEX_POST,
+            "tags": [],
+            "vers": [],
+            "req": req
+        })
+    }
+}
+
+impl Default for NoteModel {
+    fn default() -> Self {
+        let mut model = NoteModel::new("jp2anki");
+        model.add_field("Word", FieldSource::Word);
+        model.add_field("Reading", FieldSource::Reading);
+        model.add_field("Meaning", FieldSource::Definitions);
+        model.add_field("Examples", FieldSource::Examples);
+        model.add_field("Audio", FieldSource::Audio);
+
+        model.add_template(
+            "Recognition",
+            "<div class=\"word\">{{Word}}</div>",
+            "{{FrontSide}}<hr id=\"answer\">\
+                <div class=\"reading\">{{Reading}}</div>{{Audio}}\
+                <div class=\"meaning\">{{Meaning}}</div>\
+                <div class=\"examples\">{{Examples}}</div>"
+        );
+        model.add_template(
+            "Reading",
+            "<div class=\"word\">{{Word}}</div><div>Reading?</div>",
+            "<div class=\"word\">{{Word}}</div><hr id=\"answer\">\
+                <div class=\"reading\">{{Reading}}</div>{{Audio}}"
+        );
+        model.add_template(
+            "Production",
+            "<div class=\"meaning\">{{Meaning}}</div>",
+            "{{FrontSide}}<hr id=\"answer\">\
+                <div class=\"word\">{{Word}}</div>\
+                <div class=\"reading\">{{Reading}}</div>{{Audio}}"
+        );
+        model
+    }
+}
+
+fn render_field(source: FieldSource, result: &AnalyzerResult) -> String {
+    match source {
+        FieldSource::Word => escape_html(&result.word),
+        FieldSource::WordFurigana => escape_html(&furigana::to_brackets(&furigana::headword(result))),
+        FieldSource::Forms => {
+            let mut forms = BTreeSet::new();
+            for entry in &result.dict_info {
+                forms.extend(entry.forms.iter().map(String::as_str));
+            }
+            join_escaped(forms, "、")
+        },
+        FieldSource::Reading => {
+            let mut readings = BTreeSet::new();
+            for entry in &result.dict_info {
+                readings.extend(entry.readings.iter().map(String::as_str));
+            }
+            if readings.is_empty() {
+                readings.insert(result.reading.as_str());
+            }
+            join_escaped(readings, "、")
+        },
+        FieldSource::PartOfSpeech => format!("{:?}", result.pos),
+        FieldSource::Count => result.count.to_string(),
+        FieldSource::Definitions => {
+            let definitions = result.dict_info.iter()
+                .flat_map(|entry| entry.definitions.iter())
+                .map(|def| format!("<li>{}</li>", escape_html(&def.text)))
+                .collect::<String>();
+            if definitions.is_empty() {
+                definitions
+            } else {
+                format!("<ol>{}</ol>", definitions)
+            }
+        },
+        FieldSource::Examples => {
+            result.dict_info.iter()
+                .flat_map(|entry| entry.examples.iter())
+                .map(|ex| format!("{}<br>{}", escape_html(&ex.ja), escape_html(&ex.en)))
+                .collect::<Vec<_>>()
+                .join("<br><br>")
+        },
+        FieldSource::Audio => {
+            result.dict_info.iter()
+                .flat_map(|entry| entry.audio.iter())
+                .map(|url| format!("<a href=\"{0}\">{0}</a>", escape_html(url)))
+                .collect::<Vec<_>>()
+                .join("<br>")
+        },
+        FieldSource::Sentence | FieldSource::Cloze | FieldSource::Empty => String::new()
+    }
+}
+
+/// One cached recording per dictionary entry, preferring mp3 since every Anki client plays it
+fn cached_audio(result: &AnalyzerResult, cache: &AudioCache) -> Vec<String> {
+    let mut media = Vec::new();
+    for entry in &result.dict_info {
+        let cached: Vec<&str> = entry.audio.iter()
+            .filter_map(|url| cache.filename(url))
+            .collect();
+        let filename = cached.iter()
+            .find(|filename| filename.ends_with(".mp3"))
+            .or_else(|| cached.first());
+        if let Some(filename) = filename {
+            if !media.iter().any(|name| name == filename) {
+                media.push(filename.to_string());
+            }
+        }
+    }
+    media
+}
+
+fn join_escaped<'a>(items: impl IntoIterator<Item = &'a str>, sep: &str) -> String {
+    items.into_iter()
+        .map(escape_html)
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+/// Cloze notes get a card for every cloze number used in their fields, `{{c1::` is ordinal 0
+fn cloze_ordinals(note: &Note) -> Vec<usize> {
+    let mut ordinals = BTreeSet::new();
+    for field in &note.fields {
+        for (i, _) in field.match_indices("{{c") {
+            let number: String = field[i + 3..].chars()
+                .take_while(char::is_ascii_digit)
+                .collect();
+            if field[i + 3 + number.len()..].starts_with("::") {
+                if let Ok(number) = number.parse::<usize>() {
+                    ordinals.insert(number.saturating_sub(1));
+                }
+            }
+        }
+    }
+    ordinals.into_iter().collect()
+}
+
+/// Names of all fields referenced by `{{...}}` tags, including conditionals and filters
+fn template_references(template: &str) -> BTreeSet<&str> {
+    let mut references = BTreeSet::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rest = &rest[start + 2..];
+        let end = match rest.find("}}") {
+            Some(end) => end,
+            None => break
+        };
+        let tag = rest[..end].trim_start_matches(['#', '^', '/']);
+        let name = tag.rsplit(':').next().unwrap_or(tag).trim();
+        if name != "FrontSide" {
+            references.insert(name);
+        }
+        rest = &rest[end + 2..];
+    }
+    references
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use jp2anki::{Occurrence, test_util::{entry, result}};
+    use jp2anki_dict::{DictionaryEntry, Source};
+
+    use super::*;
+
+    #[test]
+    fn test_template_references() {
+        let refs = template_references("{{#Reading}}{{furigana:Reading}}{{/Reading}} {{Word}} {{FrontSide}}");
+        assert_eq!(refs.into_iter().collect::<Vec<_>>(), vec!["Reading", "Word"]);
+    }
+
+    #[test]
+    fn test_cached_audio() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = AudioCache::open(dir.path()).unwrap();
+        let mp3 = cache.insert("https://example.com/1", b"mp3", "mp3").unwrap();
+        cache.insert("https://example.com/2", b"ogg", "ogg").unwrap();
+
+        let neko = AnalyzerResult {
+            dict_info: vec![DictionaryEntry {
+                audio: vec![
+                    "https://example.com/2".into(),
+                    "https://example.com/1".into(),
+                    "https://example.com/3".into()
+                ],
+                ..entry(Source::WaniKani(1), &["猫"], &["ねこ"])
+            }],
+            ..result("猫", "ネコ")
+        };
+
+        let model = NoteModel::default();
+        let note = model.note_with_audio(&neko, Some(&cache));
+        assert_eq!(note.media, vec![mp3.clone()]);
+        assert_eq!(note.fields[4], format!("[sound:{}]", mp3));
+
+        let note = model.note(&neko);
+        assert!(note.media.is_empty());
+        assert!(note.fields[4].contains("<a href=\"https://example.com/3\">"));
+    }
+
+    #[test]
+    fn test_cloze_notes() {
+        let neko = AnalyzerResult {
+            count: 2,
+            sentences: vec!["猫がいる。".into(), "黒い猫だ。".into()],
+            occurrences: vec![
+                Occurrence {
+                    surface: "猫".into(),
+                    inflection: String::new(),
+                    byte_span: 0..3,
+                    char_span: 0..1,
+                    sentence: 0,
+                    sentence_offset: 0
+                },
+                Occurrence {
+                    surface: "猫".into(),
+                    inflection: String::new(),
+                    byte_span: 21..24,
+                    char_span: 7..8,
+                    sentence: 1,
+                    sentence_offset: "黒い".len()
+                }
+            ],
+            ..result("猫", "ネコ")
+        };
+
+        let model = NoteModel::default_cloze();
+        let notes = model.notes_with_audio(&neko, None);
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0].fields[0], "{{c1::猫}}がいる。");
+        assert_eq!(notes[1].fields[0], "黒い{{c1::猫}}だ。");
+        assert_ne!(notes[0].guid, notes[1].guid);
+        assert_eq!(model.card_ordinals(&notes[0]), vec![0]);
+        assert_eq!(model.to_json(1, 0)["type"], 1);
+    }
+
+    #[test]
+    fn test_card_ordinals() {
+        let model = NoteModel::default();
+        let neko = AnalyzerResult { count: 2, ..result("猫", "ネコ") };
+        let note = model.note(&neko);
+        assert_eq!(note.fields[0], "猫");
+        assert_eq!(note.fields[1], "ネコ");
+
+        // No definitions, so there is nothing to put on the front of a production card
+        assert_eq!(model.card_ordinals(&note), vec![0, 1]);
+    }
+}
@@ -0,0 +1,97 @@
+use std::{collections::BTreeMap, fs, io::{BufRead, BufReader, Result, Write}, path::{Path, PathBuf}};
+use sha1::{Sha1, Digest};
+
+const INDEX_FILE: &str = "index.tsv";
+
+/// Directory of downloaded pronunciation audio.
+///
+/// Files are named after the SHA1 of their content, so the same recording is
+/// only stored once and its name never clashes with other media in Anki. The
+/// index maps each `DictionaryEntry.audio` URL to the file holding it.
+pub struct AudioCache {
+    dir: PathBuf,
+    index: BTreeMap<String, String>
+}
+
+impl AudioCache {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut index = BTreeMap::new();
+        let index_path = dir.join(INDEX_FILE);
+        if index_path.exists() {
+            for line in BufReader::new(fs::File::open(index_path)?).lines() {
+                let line = line?;
+                if let Some((url, filename)) = line.split_once('\t') {
+                    index.insert(url.to_owned(), filename.to_owned());
+                }
+            }
+        }
+
+        Ok(AudioCache { dir, index })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn contains(&self, url: &str) -> bool {
+        self.index.contains_key(url)
+    }
+
+    pub fn filename(&self, url: &str) -> Option<&str> {
+        self.index.get(url).map(String::as_str)
+    }
+
+    pub fn path(&self, url: &str) -> Option<PathBuf> {
+        self.filename(url).map(|filename| self.dir.join(filename))
+    }
+
+    /// Stores the audio downloaded from `url` and returns its file name
+    pub fn insert(&mut self, url: &str, data: &[u8], extension: &str) -> Result<String> {
+        let hash = Sha1::digest(data);
+        let mut filename: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+        filename.push('.');
+        filename.push_str(extension);
+
+        let path = self.dir.join(&filename);
+        if !path.exists() {
+            fs::write(path, data)?;
+        }
+        self.index.insert(url.to_owned(), filename.clone());
+        Ok(filename)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let mut index = fs::File::create(self.dir.join(INDEX_FILE))?;
+        for (url, filename) in &self.index {
+            writeln!(index, "{}\t{}", url, filename)?;
+        }
+        Ok(())
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_cache() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut cache = AudioCache::open(dir.path()).expect("error in AudioCache::open");
+        let a = cache.insert("https://example.com/a", b"audio", "mp3").unwrap();
+        let b = cache.insert("https://example.com/b", b"audio", "mp3").unwrap();
+        assert_eq!(a, b, "identical content should share a file");
+        assert_eq!(a, "a06a492959ce12b3f0292406ec84177d07ae19b1.mp3");
+        cache.save().unwrap();
+
+        let cache = AudioCache::open(dir.path()).unwrap();
+        assert!(cache.contains("https://example.com/a"));
+        assert!(!cache.contains("https://example.com/c"));
+        assert_eq!(fs::read(cache.path("https://example.com/b").unwrap()).unwrap(), b"audio");
+    }
+}
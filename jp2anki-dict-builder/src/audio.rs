@@ -0,0 +1,81 @@
+use std::{collections::BTreeSet, fs::File, path::Path};
+
+use jp2anki_audio::AudioCache;
+use jp2anki_dict::DictionaryReader;
+use anyhow::Result;
+use reqwest::{blocking::Client, header::CONTENT_TYPE, StatusCode};
+
+fn extension(content_type: Option<&str>, url: &str) -> String {
+    match content_type {
+        Some("audio/mpeg") | Some("audio/mp3") => "mp3".into(),
+        Some("audio/ogg") => "ogg".into(),
+        Some("audio/webm") => "webm".into(),
+        Some("audio/wav") | Some("audio/x-wav") => "wav".into(),
+        _ => {
+            let path = url.split(['?', '#']).next().unwrap_or(url);
+            match path.rsplit_once('.') {
+                Some((_, ext)) if !ext.contains('/') => ext.to_ascii_lowercase(),
+                _ => "mp3".into()
+            }
+        }
+    }
+}
+
+/// Downloads the audio of every entry in the dictionary that isn't cached
+/// yet, once for every URL that entries share
+pub fn fill_audio_cache(dict_path: &Path, cache_dir: impl AsRef<Path>) -> Result<()> {
+    let mut dict = DictionaryReader::new(
+        File::open(dict_path.with_extension("idx"))?,
+        File::open(dict_path.with_extension("dat"))?
+    )?;
+    let mut cache = AudioCache::open(cache_dir)?;
+
+    let mut urls = BTreeSet::new();
+    dict.for_each_entry(|entry| {
+        urls.extend(entry.audio.into_iter().filter(|url| !cache.contains(url)));
+    })?;
+    println!("Downloading {} audio files...", urls.len());
+
+    // Whatever goes wrong, the files downloaded so far are kept
+    let downloaded = download(&urls, &mut cache);
+    cache.save()?;
+    downloaded
+}
+
+// Failed downloads are reported and skipped, so they are retried next time
+fn download(urls: &BTreeSet<String>, cache: &mut AudioCache) -> Result<()> {
+    let client = Client::new();
+    for (i, url) in urls.iter().enumerate() {
+        let resp = match client.get(url).send() {
+            Ok(resp) if resp.status() == StatusCode::OK => resp,
+            Ok(resp) => {
+                eprintln!("Error {} downloading {}", resp.status(), url);
+                continue;
+            }
+            Err(err) => {
+                eprintln!("Error downloading {}: {}", url, err);
+                continue;
+            }
+        };
+
+        let content_type = resp.headers().get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.split(';').next().unwrap_or(value).trim().to_owned());
+        let ext = extension(content_type.as_deref(), url);
+        let data = match resp.bytes() {
+            Ok(data) => data,
+            Err(err) => {
+                eprintln!("Error downloading {}: {}", url, err);
+                continue;
+            }
+        };
+        cache.insert(url, &data, &ext)?;
+
+        // Save periodically so an interrupted download doesn't lose everything
+        if (i + 1) % 100 == 0 {
+            println!("Downloaded {} audio files", i + 1);
+            cache.save()?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use std::{collections::HashMap, fs::{self, File}, io::BufWriter, path::Path};
+
+use jp2anki_dict::{DictionaryEntry, DictionaryReader, DictionaryWriter, normalize::normalize};
+use anyhow::Result;
+
+/// Values from a word list like a frequency or JLPT list, by normalized
+/// word and optionally reading. A word listed more than once keeps its
+/// smallest value.
+pub struct EntryMap<T> {
+    values: HashMap<(String, Option<String>), T>
+}
+
+impl<T: Ord + Copy> EntryMap<T> {
+    pub fn new() -> Self {
+        EntryMap { values: HashMap::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn insert(&mut self, word: &str, reading: Option<&str>, value: T) {
+        let key = (normalize(word), reading.map(normalize));
+        let min_value = self.values.entry(key).or_insert(value);
+        *min_value = value.min(*min_value);
+    }
+
+    /// The smallest value of any form of the entry, preferring values listed
+    /// together with one of its readings. Readings are looked up as words
+    /// too for kana-only and usually-kana entries, since lists have e.g.
+    /// ありがとう rather than 有難う, and for entries none of whose forms
+    /// are listed.
+    pub fn get(&self, entry: &DictionaryEntry) -> Option<T> {
+        let readings: Vec<String> = entry.readings.iter().map(|reading| normalize(reading)).collect();
+        let forms: Vec<String> = entry.forms.iter().map(|form| normalize(form)).collect();
+
+        if forms.is_empty() || entry.usually_kana() {
+            let words: Vec<String> = forms.into_iter().chain(readings.iter().cloned()).collect();
+            self.get_words(&words, &readings)
+        } else {
+            self.get_words(&forms, &readings).or_else(|| self.get_words(&readings, &readings))
+        }
+    }
+
+    fn get_words(&self, words: &[String], readings: &[String]) -> Option<T> {
+        let with_reading = words.iter()
+            .flat_map(|word| readings.iter().map(move |reading| (word.clone(), Some(reading.clone()))))
+            .filter_map(|key| self.values.get(&key))
+            .min();
+        let without_reading = words.iter()
+            .filter_map(|word| self.values.get(&(word.clone(), None)))
+            .min();
+        with_reading.or(without_reading).copied()
+    }
+}
+
+/// Rewrites an existing dictionary, passing every entry through `f` first
+pub fn update_entries(dict_path: &Path, mut f: impl FnMut(&mut DictionaryEntry)) -> Result<()> {
+    let tmp_idx = dict_path.with_extension("idx.tmp");
+    let tmp_dat = dict_path.with_extension("dat.tmp");
+
+    {
+        let mut reader = DictionaryReader::new(
+            File::open(dict_path.with_extension("idx"))?,
+            File::open(dict_path.with_extension("dat"))?
+        )?;
+        let mut writer = DictionaryWriter::new(BufWriter::new(File::create(&tmp_dat)?));
+
+        let mut result = Ok(());
+        reader.for_each_entry(|mut entry| {
+            f(&mut entry);
+            if result.is_ok() {
+                result = writer.add(entry);
+            }
+        })?;
+        result?;
+
+        writer.finish(File::create(&tmp_idx)?)?;
+    }
+
+    fs::rename(tmp_dat, dict_path.with_extension("dat"))?;
+    fs::rename(tmp_idx, dict_path.with_extension("idx"))?;
+    Ok(())
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use jp2anki_dict::{Definition, Source, test_util::entry};
+
+    use super::*;
+
+    #[test]
+    fn test_entry_map() {
+        let mut map = EntryMap::new();
+        map.insert("今日", Some("きょう"), 30);
+        map.insert("今日", Some("キョウ"), 20);
+        map.insert("今日", None, 10);
+        map.insert("ｷｮｳ", None, 5);
+        assert_eq!(map.len(), 3);
+
+        // Values listed with a reading win over smaller ones without
+        assert_eq!(map.get(&entry(Source::JMDict(1), &["今日"], &["きょう", "こんにち"])), Some(20));
+        assert_eq!(map.get(&entry(Source::JMDict(2), &["今日"], &["こんにち"])), Some(10));
+        // Kana-only entries are looked up by their readings
+        assert_eq!(map.get(&entry(Source::JMDict(3), &[], &["きょう"])), Some(5));
+        assert_eq!(map.get(&entry(Source::JMDict(4), &["明日"], &["あした"])), None);
+
+        // So are usually-kana entries and ones whose forms aren't listed
+        map.insert("有難う", None, 400);
+        map.insert("ありがとう", None, 40);
+        map.insert("とても", None, 50);
+        let usually_kana = DictionaryEntry {
+            definitions: vec![Definition::new("thank you".into(), vec![], vec!["&uk;".into()]).unwrap()],
+            ..entry(Source::JMDict(5), &["有難う"], &["ありがとう"])
+        };
+        assert_eq!(map.get(&usually_kana), Some(40));
+        assert_eq!(map.get(&entry(Source::JMDict(6), &["迚も"], &["とても"])), Some(50));
+        assert_eq!(map.get(&entry(Source::JMDict(7), &["有難う"], &["ありがとう"])), Some(400));
+    }
+
+    #[test]
+    fn test_update_entries() {
+        let dict_path = env::temp_dir().join(format!("jp2anki-update-entries-{}", process::id()));
+        let mut writer = DictionaryWriter::new(File::create(dict_path.with_extension("dat")).unwrap());
+        writer.add(entry(Source::JMDict(1), &["猫"], &["ねこ"])).unwrap();
+        writer.add(entry(Source::JMDict(2), &["犬"], &["いぬ"])).unwrap();
+        writer.finish(File::create(dict_path.with_extension("idx")).unwrap()).unwrap();
+
+        update_entries(&dict_path, |entry| if entry.forms == ["猫"] {
+            entry.frequency_rank = Some(120);
+        }).unwrap();
+
+        let mut reader = DictionaryReader::new(
+            File::open(dict_path.with_extension("idx")).unwrap(),
+            File::open(dict_path.with_extension("dat")).unwrap()
+        ).unwrap();
+        let mut ranks = Vec::new();
+        reader.for_each_entry(|entry| ranks.push((entry.source, entry.frequency_rank))).unwrap();
+        fs::remove_file(dict_path.with_extension("idx")).unwrap();
+        fs::remove_file(dict_path.with_extension("dat")).unwrap();
+
+        assert_eq!(ranks, vec![(Source::JMDict(1), Some(120)), (Source::JMDict(2), None)]);
+        assert!(reader.contains("いぬ"));
+    }
+}
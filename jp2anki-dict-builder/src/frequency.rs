@@ -0,0 +1,192 @@
+use std::{cmp::Reverse, fs, path::Path};
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::entries::EntryMap;
+
+/// Word frequency ranks from a corpus, by word and reading
+pub type FrequencyList = EntryMap<u32>;
+
+// A frequency from a term_meta_bank, with the term and reading it is for
+type BankItem = (String, Option<String>, u64);
+
+impl FrequencyList {
+    /// Reads a Yomichan `term_meta_bank_*.json` file, a directory of them
+    /// (an extracted Yomichan dictionary), or a TSV file with lines of
+    /// `word<TAB>reading<TAB>rank` or `word<TAB>rank`
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut list = FrequencyList::new();
+        let is_json = path.extension().is_some_and(|ext| ext == "json");
+        if !path.is_dir() && !is_json {
+            list.read_tsv(&fs::read_to_string(path)?);
+            return Ok(list);
+        }
+
+        let mut banks = Vec::new();
+        let dictionary_dir = if path.is_dir() {
+            for file in fs::read_dir(path)? {
+                let file = file?.path();
+                let name = file.file_name().and_then(|name| name.to_str()).unwrap_or("");
+                if name.starts_with("term_meta_bank") && name.ends_with(".json") {
+                    banks.push(file);
+                }
+            }
+            Some(path)
+        } else {
+            banks.push(path.to_owned());
+            path.parent()
+        };
+
+        let mut items = Vec::new();
+        let mut skipped = 0;
+        for bank in banks {
+            skipped += read_term_meta_bank(&fs::read_to_string(&bank)?, &mut items)?;
+        }
+        if skipped > 0 {
+            eprintln!("Skipped {} items with unsupported frequency data", skipped);
+        }
+
+        let index = dictionary_dir.map(|dir| dir.join("index.json")).filter(|index| index.is_file());
+        if let Some(index) = index {
+            if is_occurrence_based(&fs::read_to_string(index)?)? {
+                println!("Converting occurrence counts to ranks...");
+                counts_to_ranks(&mut items);
+            }
+        }
+
+        for (term, reading, rank) in items {
+            list.insert(&term, reading.as_deref(), rank as u32);
+        }
+        Ok(list)
+    }
+
+    // Lines that don't end in a rank, like headers and comments, are skipped
+    fn read_tsv(&mut self, text: &str) {
+        for line in text.lines() {
+            let columns: Vec<&str> = line.split('\t').map(str::trim).collect();
+            let (word, reading, rank) = match columns[..] {
+                [word, reading, rank] => (word, Some(reading).filter(|r| !r.is_empty()), rank),
+                [word, rank] => (word, None, rank),
+                _ => continue
+            };
+            if let Ok(rank) = rank.parse() {
+                self.insert(word, reading, rank);
+            }
+        }
+    }
+}
+
+// Each item is [term, "freq", data], where data is a rank, an object with
+// the rank as its value, or an object with a reading and one of those.
+// Returns how many frequency items had some other shape.
+fn read_term_meta_bank(json: &str, items: &mut Vec<BankItem>) -> Result<usize> {
+    let mut skipped = 0;
+    for item in serde_json::from_str::<Vec<Value>>(json)? {
+        match parse_bank_item(&item) {
+            Some(Some(item)) => items.push(item),
+            Some(None) => (),
+            None => skipped += 1
+        }
+    }
+    Ok(skipped)
+}
+
+// `Some(None)` for items of other modes, like pitch accents
+fn parse_bank_item(item: &Value) -> Option<Option<BankItem>> {
+    let [term, mode, data] = item.as_array()?.as_slice() else { return None };
+    if mode.as_str()? != "freq" {
+        return Some(None);
+    }
+    let (reading, frequency) = match data {
+        Value::Object(object) if object.contains_key("frequency") =>
+            (object.get("reading").and_then(Value::as_str), &object["frequency"]),
+        _ => (None, data)
+    };
+    let value = match frequency {
+        Value::Object(object) => object.get("value").and_then(Value::as_u64),
+        value => value.as_u64()
+    }?;
+    Some(Some((term.as_str()?.to_owned(), reading.map(str::to_owned), value)))
+}
+
+// Yomichan dictionaries declare whether their values are ranks or counts of
+// occurrences in the corpus, which grow with frequency instead
+fn is_occurrence_based(index_json: &str) -> Result<bool> {
+    let index: Value = serde_json::from_str(index_json)?;
+    Ok(index.get("frequencyMode").and_then(Value::as_str) == Some("occurrence-based"))
+}
+
+// Words with the same count share the rank of the first of them
+fn counts_to_ranks(items: &mut [BankItem]) {
+    items.sort_by_key(|item| Reverse(item.2));
+    let mut rank = 0;
+    let mut previous = None;
+    for (i, item) in items.iter_mut().enumerate() {
+        if previous != Some(item.2) {
+            rank = i as u64 + 1;
+            previous = Some(item.2);
+        }
+        item.2 = rank;
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use jp2anki_dict::{DictionaryEntry, Source, test_util};
+
+    use super::*;
+
+    fn entry(form: &str, reading: &str) -> DictionaryEntry {
+        test_util::entry(Source::JMDict(1), &[form], &[reading])
+    }
+
+    #[test]
+    fn test_read_tsv() {
+        let mut list = FrequencyList::new();
+        list.read_tsv("word\treading\trank\n猫\tねこ\t120\n猫\t\t300\n犬\t450\n# comment\n");
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.get(&entry("猫", "ねこ")), Some(120));
+        assert_eq!(list.get(&entry("猫", "びょう")), Some(300));
+        assert_eq!(list.get(&entry("犬", "いぬ")), Some(450));
+        assert_eq!(list.get(&entry("鳥", "とり")), None);
+    }
+
+    #[test]
+    fn test_read_term_meta_bank() {
+        let json = r#"[
+            ["猫", "freq", 120],
+            ["犬", "freq", {"value": 450, "displayValue": "450"}],
+            ["今日", "freq", {"reading": "きょう", "frequency": {"value": 80}}],
+            ["今日", "freq", {"reading": "こんにち", "frequency": 5000}],
+            ["猫", "pitch", {"reading": "ねこ", "pitches": []}],
+            ["鳥", "freq", "often"],
+            ["鼠"]
+        ]"#;
+        let mut items = Vec::new();
+        assert_eq!(read_term_meta_bank(json, &mut items).unwrap(), 2);
+        assert_eq!(items, vec![
+            ("猫".into(), None, 120),
+            ("犬".into(), None, 450),
+            ("今日".into(), Some("きょう".into()), 80),
+            ("今日".into(), Some("こんにち".into()), 5000),
+        ]);
+        assert!(read_term_meta_bank("{}", &mut items).is_err());
+    }
+
+    #[test]
+    fn test_occurrence_based() {
+        assert!(is_occurrence_based(r#"{"title": "Corpus", "frequencyMode": "occurrence-based"}"#).unwrap());
+        assert!(!is_occurrence_based(r#"{"title": "Corpus", "frequencyMode": "rank-based"}"#).unwrap());
+        assert!(!is_occurrence_based(r#"{"title": "Corpus"}"#).unwrap());
+
+        let mut items: Vec<BankItem> = [("猫", 10), ("犬", 900), ("鳥", 10), ("鼠", 40)].iter()
+            .map(|(term, count)| (term.to_string(), None, *count))
+            .collect();
+        counts_to_ranks(&mut items);
+        let ranks: Vec<(&str, u64)> = items.iter().map(|(term, _, rank)| (term.as_str(), *rank)).collect();
+        assert_eq!(ranks, vec![("犬", 1), ("鼠", 2), ("猫", 3), ("鳥", 3)]);
+    }
+}
@@ -0,0 +1,55 @@
+use std::{fs, path::Path};
+
+use jp2anki_dict::JlptLevel;
+use anyhow::Result;
+
+use crate::entries::EntryMap;
+
+/// JLPT levels of vocabulary, by word and reading. Words listed at several
+/// levels keep the easiest one.
+pub type JlptList = EntryMap<JlptLevel>;
+
+impl JlptList {
+    /// Reads a TSV or CSV file with lines of `word,reading,level` or
+    /// `word,level`, where levels are written like `N3` or `3`. Lines that
+    /// don't end in a level, like headers and comments, are skipped.
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut list = JlptList::new();
+        list.read_lines(&fs::read_to_string(path)?);
+        Ok(list)
+    }
+
+    fn read_lines(&mut self, text: &str) {
+        for line in text.lines() {
+            let columns: Vec<&str> = line.split(['\t', ',']).map(str::trim).collect();
+            let (word, reading, level) = match columns[..] {
+                [word, reading, level] => (word, Some(reading).filter(|r| !r.is_empty()), level),
+                [word, level] => (word, None, level),
+                _ => continue
+            };
+            if let Ok(level) = JlptLevel::try_from(level) {
+                self.insert(word, reading, level);
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use jp2anki_dict::{Source, test_util::entry};
+
+    use super::*;
+
+    #[test]
+    fn test_read_lines() {
+        let mut list = JlptList::new();
+        list.read_lines("word,reading,level\n猫,ねこ,N5\n猫,,N3\nする\tJLPT N5\n# N1 vocabulary\n犬,いぬ,N6\n");
+        assert_eq!(list.len(), 3);
+
+        assert_eq!(list.get(&entry(Source::JMDict(1), &["猫"], &["ねこ"])), Some(JlptLevel::N5));
+        assert_eq!(list.get(&entry(Source::JMDict(2), &[], &["する"])), Some(JlptLevel::N5));
+        assert_eq!(list.get(&entry(Source::JMDict(3), &["犬"], &[])), None);
+    }
+}
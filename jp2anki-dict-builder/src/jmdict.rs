@@ -1,4 +1,4 @@
-use std::{fs::File, io::{Read, BufRead, Write}, path::Path};
+use std::{collections::BTreeMap, fs::File, io::{Read, BufRead, Write}, path::Path};
 
 use jp2anki_dict::{DictionaryEntry, Definition, Example, Source, DictionaryWriter, DictError};
 use quick_xml::{events::{Event, BytesStart, attributes::Attribute, BytesEnd}, Reader};
@@ -217,6 +217,13 @@ xml_struct! {
 
 impl JMDEntry {
     fn into_dictionary_entry(self) -> Result<DictionaryEntry> {
+        let priorities: BTreeMap<String, Vec<String>> = self.k_ele.iter()
+            .map(|kanji| (&kanji.keb, &kanji.ke_pri))
+            .chain(self.r_ele.iter().map(|reading| (&reading.reb, &reading.re_pri)))
+            .filter(|(_, tags)| !tags.is_empty())
+            .map(|(word, tags)| (word.clone(), tags.clone()))
+            .collect();
+
         let forms = self.k_ele.into_iter()
             .map(|kanji| kanji.keb)
             .collect();
@@ -280,7 +287,10 @@ impl JMDEntry {
             definitions,
             audio: vec![],
             readings,
-            examples 
+            examples,
+            priorities,
+            frequency_rank: None,
+            jlpt_level: None
         })
     }
 }
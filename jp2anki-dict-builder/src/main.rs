@@ -2,6 +2,10 @@
 
 mod wanikani;
 mod jmdict;
+mod audio;
+mod entries;
+mod frequency;
+mod jlpt;
 
 use std::{path::Path, fs::File, io::BufWriter};
 use jp2anki_dict::DictionaryWriter;
@@ -16,29 +20,76 @@ struct Args {
     jmdict_path: Option<String>,
     #[clap(short, long, value_parser, default_value_t = String::from("dictionary"))]
     dict_name: String,
+    #[clap(short, long, value_parser)]
+    audio_cache: Option<String>,
+    /// TSV or Yomichan frequency list to rank the dictionary's entries by
+    #[clap(short, long, value_parser)]
+    frequency_list: Option<String>,
+    /// TSV or CSV list of JLPT vocabulary to tag the dictionary's entries with
+    #[clap(short = 'l', long, value_parser)]
+    jlpt_list: Option<String>,
 }
 
 fn main() {
     let args = Args::parse();
 
     let path = Path::new(&args.dict_name);
-    let dat_fp = File::create(&path.with_extension("dat")).unwrap();
-    let mut dict = DictionaryWriter::new(BufWriter::new(dat_fp));
-        
-    if let Some(token) = &args.token {
-        println!("Updating WaniKani entries...");
-        wanikani::update_wanikani(&mut dict, token).unwrap();
-    }
 
-    if let Some(ref jmdict_path) = args.jmdict_path {
-        let jmdict_path = Path::new(jmdict_path);
-        if jmdict_path.exists() {
-            println!("Updating JMDict entries...");
-            jmdict::update_jmdict(&mut dict, jmdict_path).unwrap();
+    // Without any sources only the frequency ranks, JLPT levels and audio
+    // cache are updated, from the existing dictionary
+    if args.token.is_some() || args.jmdict_path.is_some() {
+        let dat_fp = File::create(&path.with_extension("dat")).unwrap();
+        let mut dict = DictionaryWriter::new(BufWriter::new(dat_fp));
+            
+        if let Some(token) = &args.token {
+            println!("Updating WaniKani entries...");
+            wanikani::update_wanikani(&mut dict, token).unwrap();
+        }
+
+        if let Some(ref jmdict_path) = args.jmdict_path {
+            let jmdict_path = Path::new(jmdict_path);
+            if jmdict_path.exists() {
+                println!("Updating JMDict entries...");
+                jmdict::update_jmdict(&mut dict, jmdict_path).unwrap();
+            }
         }
+
+        println!("Saving dictionary...");
+        let idx_fp = File::create(path.with_extension("idx")).unwrap();
+        dict.finish(idx_fp).unwrap();
+    }
+
+    let frequency_list = args.frequency_list.as_ref().map(|frequency_list| {
+        println!("Reading frequency list...");
+        let list = frequency::FrequencyList::read(Path::new(frequency_list)).unwrap();
+        println!("Ranking entries by {} words...", list.len());
+        list
+    });
+    let jlpt_list = args.jlpt_list.as_ref().map(|jlpt_list| {
+        println!("Reading JLPT list...");
+        let list = jlpt::JlptList::read(Path::new(jlpt_list)).unwrap();
+        println!("Tagging entries with the levels of {} words...", list.len());
+        list
+    });
+    if frequency_list.is_some() || jlpt_list.is_some() {
+        let (mut ranked, mut tagged) = (0, 0);
+        // Values from an earlier run are replaced, so entries missing from
+        // the new list don't keep their old rank or level
+        entries::update_entries(path, |entry| {
+            if let Some(list) = &frequency_list {
+                entry.frequency_rank = list.get(entry);
+                ranked += entry.frequency_rank.is_some() as usize;
+            }
+            if let Some(list) = &jlpt_list {
+                entry.jlpt_level = list.get(entry);
+                tagged += entry.jlpt_level.is_some() as usize;
+            }
+        }).unwrap();
+        println!("Ranked {} entries, tagged {} with a JLPT level", ranked, tagged);
     }
 
-    println!("Saving dictionary...");
-    let idx_fp = File::create(path.with_extension("idx")).unwrap();
-    dict.finish(idx_fp).unwrap();
+    if let Some(ref audio_cache) = args.audio_cache {
+        println!("Updating audio cache...");
+        audio::fill_audio_cache(path, audio_cache).unwrap();
+    }
 }
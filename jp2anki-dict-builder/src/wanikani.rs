@@ -114,6 +114,9 @@ impl WkSubject {
                     audio,
                     readings,
                     examples,
+                    priorities: Default::default(),
+                    frequency_rank: None,
+                    jlpt_level: None
                 }))
             },
             _ => Ok(None)
@@ -1,12 +1,24 @@
+pub mod normalize;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+
 use std::{collections::{HashMap, BTreeMap, HashSet, BTreeSet}, io::{Seek, Write, SeekFrom, Read, Cursor}};
 use flate2::{write::DeflateEncoder, Compression, read::DeflateDecoder};
 use regex::Regex;
 use serde::{Serialize, Deserialize};
 use thiserror::Error;
 use lazy_static::lazy_static;
+use normalize::normalize;
 
 const ENTRIES_PER_CHUNK: usize = 32;
 const COMPRESSION_LEVEL: Compression = Compression::best();
+// Start of the index and data files, so that files built by an older builder
+// are rejected instead of misread
+const MAGIC: [u8; 4] = *b"J2AD";
+const HEADER_LEN: usize = 8;
+/// Version of the dictionary files, bumped whenever the index or the fields
+/// of `DictionaryEntry` change
+pub const FORMAT_VERSION: u32 = 2;
 
 #[derive(Debug, Error)]
 pub enum DictError {
@@ -15,7 +27,13 @@ pub enum DictError {
     #[error("IO error: {0}")]
     IO(#[from] std::io::Error),
     #[error("Unknown part of speech: {0:?}")]
-    UnknownPOS(String)
+    UnknownPOS(String),
+    #[error("Unknown JLPT level: {0:?}")]
+    UnknownJlptLevel(String),
+    #[error("Not a dictionary file, or one built before it was versioned; rebuild it with jp2anki-dict-builder")]
+    MissingHeader,
+    #[error("Dictionary format version {0} is not supported (expected {FORMAT_VERSION}); rebuild it with jp2anki-dict-builder")]
+    UnsupportedVersion(u32)
 }
 
 pub type Result<T, E=DictError> = std::result::Result<T, E>;
@@ -31,14 +49,44 @@ pub enum Source {
 }
 
 #[derive(
-    Serialize, Deserialize, Debug, 
+    Serialize, Deserialize, Debug, Default,
     PartialEq, Eq, PartialOrd, Ord, 
     Hash, Clone, Copy
 )]
 pub enum PartOfSpeech {
     Noun, Prefix, Verb, Adjective, Adverb,
     Adnominal, Conjuction, Particle, AuxiliaryVerb,
-    Exclamation, Symbol, Filler, Other
+    Exclamation, Symbol, Filler,
+    #[default]
+    Other
+}
+
+/// JLPT level of a word, ordered from the easiest level N5 to the hardest N1
+#[derive(
+    Serialize, Deserialize, Debug, 
+    PartialEq, Eq, PartialOrd, Ord, 
+    Hash, Clone, Copy
+)]
+pub enum JlptLevel {
+    N5, N4, N3, N2, N1
+}
+
+impl TryFrom<&str> for JlptLevel {
+    type Error = DictError;
+
+    /// Accepts levels written like "N3", "n3", "JLPT N3" or "3"
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let level = value.trim().trim_start_matches("JLPT").trim();
+        let level = level.strip_prefix(['N', 'n']).unwrap_or(level);
+        Ok(match level {
+            "5" => JlptLevel::N5,
+            "4" => JlptLevel::N4,
+            "3" => JlptLevel::N3,
+            "2" => JlptLevel::N2,
+            "1" => JlptLevel::N1,
+            _ => return Err(DictError::UnknownJlptLevel(value.into()))
+        })
+    }
 }
 
 lazy_static! {
@@ -58,7 +106,7 @@ impl TryFrom<&str> for PartOfSpeech {
                 => PartOfSpeech::Prefix,
             "動詞" | "&cop;"
                 => PartOfSpeech::Verb,
-            "見出し形"
+            "形容詞" | "見出し形"
                 => PartOfSpeech::Adjective,
             "副詞" | "&adv;" | "&adv-to;"
                 => PartOfSpeech::Adverb,
@@ -96,7 +144,13 @@ pub struct DictionaryEntry {
     pub definitions: Vec<Definition>,
     pub audio: Vec<String>,
     pub readings: Vec<String>,
-    pub examples: Vec<Example>
+    pub examples: Vec<Example>,
+    /// JMDict priority tags like news1, ichi1 or nf12, by form or reading
+    pub priorities: BTreeMap<String, Vec<String>>,
+    /// Position of the word in a corpus frequency list, where 1 is the most
+    /// common. Priority tags give a coarser rank for entries without one.
+    pub frequency_rank: Option<u32>,
+    pub jlpt_level: Option<JlptLevel>
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -113,6 +167,37 @@ pub struct Example {
     pub ja: String
 }
 
+/// Rough frequency rank implied by JMDict priority tags. nfXX tags place a
+/// word in the XXth block of 500 words of a newspaper frequency list, while
+/// the other *1 tags cover about the 12,000 most common words.
+pub fn priority_rank(tags: &[String]) -> Option<u32> {
+    tags.iter()
+        .filter_map(|tag| match tag.as_str() {
+            tag if tag.starts_with("nf") => tag[2..].parse::<u32>().ok().map(|block| block * 500),
+            "news1" | "ichi1" | "spec1" | "gai1" => Some(12000),
+            "news2" | "ichi2" | "spec2" | "gai2" => Some(24000),
+            _ => None
+        })
+        .min()
+}
+
+impl DictionaryEntry {
+    /// The frequency rank of the entry, or else the priority rank of `word`
+    /// if it is a form or reading with priority tags, or else the best
+    /// priority rank of the entry
+    pub fn rank_of(&self, word: &str) -> Option<u32> {
+        self.frequency_rank
+            .or_else(|| self.priorities.get(word).and_then(|tags| priority_rank(tags)))
+            .or_else(|| self.priorities.values().filter_map(|tags| priority_rank(tags)).min())
+    }
+
+    /// Whether a definition is flagged as usually written in kana, like
+    /// ありがとう for 有難う
+    pub fn usually_kana(&self) -> bool {
+        self.definitions.iter().any(|definition| definition.flags.iter().any(|flag| flag == "&uk;"))
+    }
+}
+
 impl Definition {
     pub fn new(text: String, pos: Vec<String>, flags: Vec<String>) -> Result<Self> {
         let pos = pos.into_iter()
@@ -133,6 +218,8 @@ pub struct DictionaryWriter<W: Write> {
     index: BTreeMap<String, BTreeSet<u32>>,
     entry_buffer: Vec<DictionaryEntry>,
     data_position: usize,
+    // The header is written with the first chunk, so that `new` can't fail
+    header_written: bool,
     data: W,
 }
 
@@ -141,14 +228,15 @@ impl<W: Write> DictionaryWriter<W> {
         DictionaryWriter {
             index: Default::default(),
             entry_buffer: Vec::new(),
-            data_position: 0,
+            data_position: HEADER_LEN,
+            header_written: false,
             data
         }
     }
 
     pub fn add(&mut self, entry: DictionaryEntry) -> Result<()> {
         for word in entry.forms.iter().chain(entry.readings.iter()) {
-            self.index.entry(word.clone())
+            self.index.entry(normalize(word))
                 .or_default()
                 .insert(self.data_position as u32);
         }
@@ -162,6 +250,10 @@ impl<W: Write> DictionaryWriter<W> {
     }
 
     pub fn flush(&mut self) -> Result<()> {
+        if !self.header_written {
+            write_header(&mut self.data)?;
+            self.header_written = true;
+        }
         if self.entry_buffer.len() == 0 {
             return Ok(());
         }
@@ -182,21 +274,48 @@ impl<W: Write> DictionaryWriter<W> {
         Ok(())
     }
 
-    pub fn finish<W2: Write>(mut self, index_write: W2) -> Result<()> {
+    pub fn finish<W2: Write>(mut self, mut index_write: W2) -> Result<()> {
         self.flush()?;
+        write_header(&mut index_write)?;
         let index_write = DeflateEncoder::new(index_write, COMPRESSION_LEVEL);
         bincode::serialize_into(index_write, &self.index)?;
         Ok(())
     }
 }
 
+fn write_header(write: &mut impl Write) -> Result<()> {
+    write.write_all(&MAGIC)?;
+    write.write_all(&FORMAT_VERSION.to_be_bytes())?;
+    Ok(())
+}
+
+fn read_header(read: &mut impl Read) -> Result<()> {
+    let mut header = [0u8; HEADER_LEN];
+    match read.read_exact(&mut header) {
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Err(DictError::MissingHeader),
+        result => result?
+    }
+    if header[..4] != MAGIC {
+        return Err(DictError::MissingHeader);
+    }
+    match u32::from_be_bytes(header[4..].try_into().unwrap()) {
+        FORMAT_VERSION => Ok(()),
+        version => Err(DictError::UnsupportedVersion(version))
+    }
+}
+
 pub struct DictionaryReader<R: Read + Seek> {
     index: BTreeMap<String, Vec<u32>>,
     data: R
 }
 
 impl<R: Read + Seek> DictionaryReader<R> {
-    pub fn new(index_read: impl Read, data: R) -> Result<Self> {
+    /// Fails with `MissingHeader` or `UnsupportedVersion` for files that
+    /// weren't written by this version's `DictionaryWriter`
+    pub fn new(mut index_read: impl Read, mut data: R) -> Result<Self> {
+        read_header(&mut index_read)?;
+        data.seek(SeekFrom::Start(0))?;
+        read_header(&mut data)?;
         let index_read = DeflateDecoder::new(index_read);
         Ok(DictionaryReader {
             index: bincode::deserialize_from(index_read)?,
@@ -204,12 +323,20 @@ impl<R: Read + Seek> DictionaryReader<R> {
         })
     }
 
+    /// Whether any entry has `word` as a form or reading, without reading the entries
+    pub fn contains(&self, word: &str) -> bool {
+        self.index.contains_key(&normalize(word))
+    }
+
+    /// Entries with a form or reading matching each word, compared in their
+    /// normalized form so that e.g. katakana spellings find hiragana words
     pub fn lookup<'a>(&mut self, words: &[&'a str]) -> Result<HashMap<&'a str, Vec<DictionaryEntry>>> {
-        let mut chunks: HashMap<u32, Vec<&'a str>> = HashMap::new();
+        let mut chunks: HashMap<u32, Vec<(&'a str, String)>> = HashMap::new();
         for word in words {
-            if let Some(entry_chunks) = self.index.get(*word) {
+            let key = normalize(word);
+            if let Some(entry_chunks) = self.index.get(&key) {
                 for chunk_position in entry_chunks {
-                    chunks.entry(*chunk_position).or_default().push(word);
+                    chunks.entry(*chunk_position).or_default().push((word, key.clone()));
                 }
             }
         }
@@ -218,8 +345,12 @@ impl<R: Read + Seek> DictionaryReader<R> {
         for (chunk_position, words) in &chunks {
             let chunk = self.read_chunk(*chunk_position)?;
             for entry in chunk {
-                for word in words {
-                    if entry.forms.iter().any(|form| form == word) || entry.readings.iter().any(|reading| reading == word) {
+                let keys: HashSet<String> = entry.forms.iter()
+                    .chain(entry.readings.iter())
+                    .map(|word| normalize(word))
+                    .collect();
+                for (word, key) in words {
+                    if keys.contains(key) {
                         result.entry(*word)
                             .or_default()
                             .push(entry.clone());
@@ -231,6 +362,22 @@ impl<R: Read + Seek> DictionaryReader<R> {
         Ok(result)
     }
 
+    /// Calls `f` with every entry in the dictionary, in the order they were added
+    pub fn for_each_entry(&mut self, mut f: impl FnMut(DictionaryEntry)) -> Result<()> {
+        let chunk_positions: BTreeSet<u32> = self.index.values()
+            .flatten()
+            .copied()
+            .collect();
+
+        for chunk_position in chunk_positions {
+            for entry in self.read_chunk(chunk_position)? {
+                f(entry);
+            }
+        }
+
+        Ok(())
+    }
+
     fn read_chunk(&mut self, pos: u32) -> Result<Vec<DictionaryEntry>> {
         self.data.seek(SeekFrom::Start(pos as u64))?;
 
@@ -266,7 +413,10 @@ mod tests {
             definitions: vec![],
             audio: vec![],
             readings: vec![],
-            examples: vec![]
+            examples: vec![],
+            priorities: BTreeMap::new(),
+            frequency_rank: None,
+            jlpt_level: None
         }).expect("error in dict_w.add");
         dict_w.finish(&mut idx_file)
             .expect("error in dict_w.finish");
@@ -276,8 +426,8 @@ mod tests {
         assert!(dat_file.len() > 0);
         assert!(idx_file.len() > 0);
 
-        let len = u32::from_be_bytes(dat_file[0..4].try_into().unwrap());
-        assert_eq!(len as usize + 4, dat_file.len());
+        let len = u32::from_be_bytes(dat_file[HEADER_LEN..HEADER_LEN + 4].try_into().unwrap());
+        assert_eq!(HEADER_LEN + 4 + len as usize, dat_file.len());
     }
 
     #[test]
@@ -292,7 +442,10 @@ mod tests {
             definitions: vec![],
             audio: vec![],
             readings: vec![],
-            examples: vec![]
+            examples: vec![],
+            priorities: BTreeMap::new(),
+            frequency_rank: None,
+            jlpt_level: None
         }).expect("error in dict_w.add#1");
         dict_w.add(DictionaryEntry {
             forms: vec!["X".into(), "YX".into(), "Q".into()],
@@ -300,7 +453,10 @@ mod tests {
             definitions: vec![],
             audio: vec![],
             readings: vec![],
-            examples: vec![]
+            examples: vec![],
+            priorities: BTreeMap::new(),
+            frequency_rank: None,
+            jlpt_level: None
         }).expect("error in dict_w.add#2");
         dict_w.finish(&mut idx_file)
             .expect("error in dict_w.finish");
@@ -330,4 +486,73 @@ mod tests {
         assert_eq!(result_sources["X"], vec![Source::JMDict(2)], "lookup X");
         assert_eq!(result_sources["Q"], vec![Source::JMDict(1), Source::JMDict(2)], "lookup Q");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_lookup_normalized() {
+        let mut dat_file = Vec::new();
+        let mut idx_file = Vec::new();
+
+        let mut dict_w = DictionaryWriter::new(&mut dat_file);
+        dict_w.add(DictionaryEntry {
+            forms: vec![],
+            source: Source::JMDict(1),
+            definitions: vec![],
+            audio: vec![],
+            readings: vec!["かたかな".into()],
+            examples: vec![],
+            priorities: BTreeMap::new(),
+            frequency_rank: None,
+            jlpt_level: None
+        }).expect("error in dict_w.add");
+        dict_w.finish(&mut idx_file)
+            .expect("error in dict_w.finish");
+
+        let mut dict_r = DictionaryReader::new(
+            Cursor::new(idx_file),
+            Cursor::new(dat_file)
+        ).expect("error in DictionaryReader::new");
+        let result = dict_r.lookup(&["カタカナ", "ｶﾀｶﾅ", "かたかな", "ひらがな"])
+            .expect("error in dict_r.lookup");
+
+        assert_eq!(result.len(), 3);
+        assert!(result.contains_key("ｶﾀｶﾅ"));
+        assert!(dict_r.contains("カタカナ"));
+    }
+
+    #[test]
+    fn test_format_version() {
+        let mut dat_file = Vec::new();
+        let mut idx_file = Vec::new();
+        DictionaryWriter::new(&mut dat_file).finish(&mut idx_file)
+            .expect("error in dict_w.finish");
+        assert!(DictionaryReader::new(Cursor::new(&idx_file), Cursor::new(&dat_file)).is_ok());
+
+        // Files from before the header was added
+        let unversioned = Cursor::new(&idx_file[HEADER_LEN..]);
+        assert!(matches!(DictionaryReader::new(unversioned, Cursor::new(&dat_file)), Err(DictError::MissingHeader)));
+        assert!(matches!(DictionaryReader::new(Cursor::new(Vec::new()), Cursor::new(&dat_file)), Err(DictError::MissingHeader)));
+        let unversioned = Cursor::new(&dat_file[HEADER_LEN..]);
+        assert!(matches!(DictionaryReader::new(Cursor::new(&idx_file), unversioned), Err(DictError::MissingHeader)));
+
+        idx_file[4..HEADER_LEN].copy_from_slice(&(FORMAT_VERSION + 1).to_be_bytes());
+        let newer = DictionaryReader::new(Cursor::new(&idx_file), Cursor::new(&dat_file));
+        assert!(matches!(newer, Err(DictError::UnsupportedVersion(version)) if version == FORMAT_VERSION + 1));
+    }
+
+    #[test]
+    fn test_priority_rank() {
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<String>>();
+        assert_eq!(priority_rank(&tags(&["news1", "nf03"])), Some(1500));
+        assert_eq!(priority_rank(&tags(&["spec2"])), Some(24000));
+        assert_eq!(priority_rank(&tags(&[])), None);
+    }
+
+    #[test]
+    fn test_jlpt_level() {
+        assert_eq!(JlptLevel::try_from("N5").unwrap(), JlptLevel::N5);
+        assert_eq!(JlptLevel::try_from("JLPT n2").unwrap(), JlptLevel::N2);
+        assert_eq!(JlptLevel::try_from("1").unwrap(), JlptLevel::N1);
+        assert!(JlptLevel::try_from("level").is_err());
+        assert!(JlptLevel::N5 < JlptLevel::N1);
+    }
+}
@@ -0,0 +1,108 @@
+/// Hiragana by the vowel they end in, for expanding the long vowel mark
+const VOWEL_ROWS: [(char, &str); 5] = [
+    ('あ', "あかさたなはまやらわがざだばぱぁゃゎ"),
+    ('い', "いきしちにひみりぎじぢびぴぃ"),
+    ('う', "うくすつぬふむゆるぐずづぶぷぅゅゔ"),
+    ('え', "えけせてねへめれげぜでべぺぇ"),
+    ('お', "おこそとのほもよろをごぞどぼぽぉょ"),
+];
+
+const HALF_WIDTH_KATAKANA: &str = "。「」、・ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン";
+
+/// Form of a word used as an index key, so that spellings differing only in
+/// script or width find the same entries: カタカナ, ｶﾀｶﾅ and かたかな all
+/// give かたかな.
+///
+/// Half-width katakana and full-width ASCII are converted to their usual
+/// width, katakana to hiragana, the long vowel mark to the vowel it extends
+/// and the iteration marks 々, ゝ and ゞ to the character they repeat.
+pub fn normalize(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut prev = None;
+    for c in normalize_width(text) {
+        let c = match (c, prev) {
+            ('々', Some(prev)) => prev,
+            ('ゝ' | 'ヽ', Some(prev)) => unvoiced(prev),
+            ('ゞ' | 'ヾ', Some(prev)) => voiced(unvoiced(prev)),
+            ('ー', Some(prev)) => vowel(prev).unwrap_or(c),
+            ('ァ'..='ヶ', _) => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            _ => c
+        };
+        result.push(c);
+        prev = Some(c);
+    }
+    result
+}
+
+fn normalize_width(text: &str) -> Vec<char> {
+    let mut result: Vec<char> = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '！'..='～' => result.push(char::from_u32(c as u32 - 0xfee0).unwrap_or(c)),
+            '\u{3000}' => result.push(' '),
+            '｡'..='ﾝ' => result.push(HALF_WIDTH_KATAKANA.chars().nth(c as usize - '｡' as usize).unwrap_or(c)),
+            'ﾞ' => match result.last_mut() {
+                Some(prev) if voiced(*prev) != *prev => *prev = voiced(*prev),
+                _ => result.push('゛')
+            },
+            'ﾟ' => match result.last_mut() {
+                Some(prev @ ('ハ' | 'ヒ' | 'フ' | 'ヘ' | 'ホ')) => *prev = char::from_u32(*prev as u32 + 2).unwrap(),
+                _ => result.push('゜')
+            },
+            c => result.push(c)
+        }
+    }
+    result
+}
+
+/// The voiced kana for か-, さ-, た- and は-row kana and う, or `c` itself
+fn voiced(c: char) -> char {
+    match c {
+        'う' => 'ゔ',
+        'ウ' => 'ヴ',
+        'か' | 'き' | 'く' | 'け' | 'こ' | 'さ' | 'し' | 'す' | 'せ' | 'そ' | 'た' | 'ち' | 'つ' | 'て' | 'と'
+        | 'カ' | 'キ' | 'ク' | 'ケ' | 'コ' | 'サ' | 'シ' | 'ス' | 'セ' | 'ソ' | 'タ' | 'チ' | 'ツ' | 'テ' | 'ト'
+        | 'は' | 'ひ' | 'ふ' | 'へ' | 'ほ' | 'ハ' | 'ヒ' | 'フ' | 'ヘ' | 'ホ'
+            => char::from_u32(c as u32 + 1).unwrap(),
+        c => c
+    }
+}
+
+fn unvoiced(c: char) -> char {
+    match c {
+        'ゔ' => 'う',
+        'ヴ' => 'ウ',
+        'が' | 'ぎ' | 'ぐ' | 'げ' | 'ご' | 'ざ' | 'じ' | 'ず' | 'ぜ' | 'ぞ' | 'だ' | 'ぢ' | 'づ' | 'で' | 'ど'
+        | 'ガ' | 'ギ' | 'グ' | 'ゲ' | 'ゴ' | 'ザ' | 'ジ' | 'ズ' | 'ゼ' | 'ゾ' | 'ダ' | 'ヂ' | 'ヅ' | 'デ' | 'ド'
+        | 'ば' | 'び' | 'ぶ' | 'べ' | 'ぼ' | 'バ' | 'ビ' | 'ブ' | 'ベ' | 'ボ'
+            => char::from_u32(c as u32 - 1).unwrap(),
+        'ぱ' | 'ぴ' | 'ぷ' | 'ぺ' | 'ぽ' | 'パ' | 'ピ' | 'プ' | 'ペ' | 'ポ'
+            => char::from_u32(c as u32 - 2).unwrap(),
+        c => c
+    }
+}
+
+fn vowel(c: char) -> Option<char> {
+    VOWEL_ROWS.iter()
+        .find(|(_, row)| row.contains(c))
+        .map(|(vowel, _)| *vowel)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("カタカナ"), "かたかな");
+        assert_eq!(normalize("ｶﾀｶﾅ"), "かたかな");
+        assert_eq!(normalize("ｶﾞｯｺｳ ﾊﾟﾝ"), "がっこう ぱん");
+        assert_eq!(normalize("ＡＢＣ１２３"), "ABC123");
+        assert_eq!(normalize("ケーキ"), "けえき");
+        assert_eq!(normalize("時々"), "時時");
+        assert_eq!(normalize("いすゞ"), "いすず");
+        assert_eq!(normalize("こゝろ"), "こころ");
+    }
+}
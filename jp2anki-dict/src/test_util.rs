@@ -0,0 +1,21 @@
+//! Builders for the fixtures of tests in this and the dependent crates
+
+use std::collections::BTreeMap;
+
+use crate::{DictionaryEntry, Source};
+
+/// An entry with only forms and readings. Tests set the rest with struct
+/// update syntax.
+pub fn entry(source: Source, forms: &[&str], readings: &[&str]) -> DictionaryEntry {
+    DictionaryEntry {
+        forms: forms.iter().map(|form| form.to_string()).collect(),
+        source,
+        definitions: Vec::new(),
+        audio: Vec::new(),
+        readings: readings.iter().map(|reading| reading.to_string()).collect(),
+        examples: Vec::new(),
+        priorities: BTreeMap::new(),
+        frequency_rank: None,
+        jlpt_level: None
+    }
+}
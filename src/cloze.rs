@@ -0,0 +1,127 @@
+use std::collections::HashSet;
+use serde::{Serialize, Deserialize};
+
+use crate::AnalyzerResult;
+
+/// A sentence from the analyzed text with the occurrences of a word blanked out
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ClozeSentence {
+    pub sentence: String,
+    /// The sentence in Anki cloze syntax, e.g. `{{c1::食べた::to eat}}`
+    pub text: String
+}
+
+/// Wraps `text` in a first cloze deletion, with an optional hint
+pub fn cloze(text: &str, hint: Option<&str>) -> String {
+    match hint {
+        Some(hint) => format!("{{{{c1::{}::{}}}}}", text, escape_hint(hint)),
+        None => format!("{{{{c1::{}}}}}", text)
+    }
+}
+
+// "::" and "}}" would end the hint early, and so would a "}" at its end
+// together with the closing braces. Runs of colons are merged and braces
+// spaced apart.
+fn escape_hint(hint: &str) -> String {
+    let mut escaped = String::with_capacity(hint.len());
+    for c in hint.chars() {
+        match c {
+            ':' if escaped.ends_with(':') => continue,
+            '}' if escaped.ends_with('}') => escaped.push(' '),
+            _ => ()
+        }
+        escaped.push(c);
+    }
+    if escaped.ends_with('}') {
+        escaped.push(' ');
+    }
+    escaped
+}
+
+/// One cloze per distinct sentence the word occurs in. Conjugated occurrences are
+/// blanked out together with their auxiliaries, and the first dictionary
+/// definition is used as the hint.
+pub fn cloze_sentences(result: &AnalyzerResult) -> Vec<ClozeSentence> {
+    let hint = result.dict_info.iter()
+        .flat_map(|entry| entry.definitions.first())
+        .map(|definition| definition.text.as_str())
+        .next();
+
+    let mut seen = HashSet::new();
+    result.sentences.iter()
+        .enumerate()
+        .filter(|(_, sentence)| seen.insert(sentence.as_str()))
+        .map(|(i, sentence)| {
+            let mut occurrences: Vec<_> = result.occurrences.iter()
+                .filter(|occurrence| occurrence.sentence == i)
+                .collect();
+            occurrences.sort_by_key(|occurrence| occurrence.sentence_offset);
+
+            let mut text = String::new();
+            let mut pos = 0;
+            for occurrence in occurrences {
+                let start = occurrence.sentence_offset;
+                let end = start + occurrence.surface.len() + occurrence.inflection.len();
+                if start < pos || sentence.get(start..end).is_none() {
+                    continue;
+                }
+                text.push_str(&sentence[pos..start]);
+                text.push_str(&cloze(&sentence[start..end], hint));
+                pos = end;
+            }
+            text.push_str(&sentence[pos..]);
+
+            ClozeSentence { sentence: sentence.clone(), text }
+        })
+        .collect()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use jp2anki_dict::{DictionaryEntry, Definition, PartOfSpeech, Source};
+
+    use super::*;
+    use crate::{Occurrence, test_util::{entry, result}};
+
+    fn occurrence(surface: &str, inflection: &str, sentence: usize, sentence_offset: usize) -> Occurrence {
+        Occurrence {
+            inflection: inflection.into(),
+            ..crate::test_util::occurrence(surface, sentence, 0, sentence_offset)
+        }
+    }
+
+    #[test]
+    fn test_cloze_sentences() {
+        let taberu = AnalyzerResult {
+            pos: PartOfSpeech::Verb,
+            count: 3,
+            dict_info: vec![DictionaryEntry {
+                definitions: vec![
+                    Definition::new("to eat".into(), vec!["&v1;".into()], vec![]).unwrap()
+                ],
+                ..entry(Source::JMDict(1358280), &["食べる"], &["たべる"])
+            }],
+            sentences: vec!["パンを食べた。".into(), "食べて、また食べる。".into()],
+            occurrences: vec![
+                occurrence("食べ", "た", 0, "パンを".len()),
+                occurrence("食べ", "て", 1, 0),
+                occurrence("食べる", "", 1, "食べて、また".len())
+            ],
+            ..result("食べる", "タベ")
+        };
+
+        let clozes = cloze_sentences(&taberu);
+        assert_eq!(clozes[0].text, "パンを{{c1::食べた::to eat}}。");
+        assert_eq!(clozes[1].text, "{{c1::食べて::to eat}}、また{{c1::食べる::to eat}}。");
+    }
+
+    #[test]
+    fn test_cloze_hint_escaping() {
+        assert_eq!(cloze("猫", Some("cat::feline}}")), "{{c1::猫::cat:feline} } }}");
+        assert_eq!(cloze("猫", Some("{cat}")), "{{c1::猫::{cat} }}");
+        assert_eq!(cloze("猫", Some("a:::b}}}c")), "{{c1::猫::a:b} } }c}}");
+        assert_eq!(cloze("猫", None), "{{c1::猫}}");
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::HashSet;
+use jp2anki_dict::{DictionaryEntry, PartOfSpeech};
+use lazy_static::lazy_static;
+use serde::{Serialize, Deserialize};
+
+/// Set of word classes a term can belong to while it is being deinflected
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WordClass(u8);
+
+impl WordClass {
+    /// The text as found, which any rule may apply to
+    pub const ANY: WordClass = WordClass(0);
+    pub const ICHIDAN: WordClass = WordClass(1);
+    pub const GODAN: WordClass = WordClass(1 << 1);
+    pub const SURU: WordClass = WordClass(1 << 2);
+    pub const KURU: WordClass = WordClass(1 << 3);
+    pub const ADJECTIVE: WordClass = WordClass(1 << 4);
+    /// Te-form, which auxiliaries like いる and しまう attach to
+    pub const TE: WordClass = WordClass(1 << 5);
+    /// Polite ます-form
+    pub const MASU: WordClass = WordClass(1 << 6);
+
+    const VERB: WordClass = WordClass(
+        WordClass::ICHIDAN.0 | WordClass::GODAN.0 | WordClass::SURU.0 | WordClass::KURU.0
+    );
+
+    fn intersects(self, other: WordClass) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Whether a dictionary word of part of speech `pos` can belong to the class
+    pub fn matches(self, pos: PartOfSpeech) -> bool {
+        match pos {
+            _ if self == WordClass::ANY => true,
+            PartOfSpeech::Verb => self.intersects(WordClass::VERB),
+            PartOfSpeech::Adjective => self.intersects(WordClass::ADJECTIVE),
+            _ => false
+        }
+    }
+}
+
+struct Rule {
+    from: String,
+    to: String,
+    class_in: WordClass,
+    class_out: WordClass,
+    reason: &'static str
+}
+
+/// A candidate dictionary form of a conjugated word
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Deinflection {
+    pub term: String,
+    /// The conjugations leading from the dictionary form to the text, in the
+    /// order they apply: 食べていた gives `["te-iru", "past"]`
+    pub reasons: Vec<String>,
+    #[serde(skip)]
+    pub class: WordClass
+}
+
+impl Deinflection {
+    /// Whether `entry` is a word of the class the deinflected term belongs to
+    pub fn matches_entry(&self, entry: &DictionaryEntry) -> bool {
+        self.class == WordClass::ANY || entry.definitions.iter()
+            .any(|definition| definition.pos.is_empty() || definition.pos.iter().any(|pos| self.class.matches(*pos)))
+    }
+}
+
+// Dictionary ending, then its i-, a-, e- and o-stems and its te- and ta-forms
+const GODAN_ENDINGS: [[&str; 7]; 9] = [
+    ["う", "い", "わ", "え", "お", "って", "った"],
+    ["く", "き", "か", "け", "こ", "いて", "いた"],
+    ["ぐ", "ぎ", "が", "げ", "ご", "いで", "いだ"],
+    ["す", "し", "さ", "せ", "そ", "して", "した"],
+    ["つ", "ち", "た", "て", "と", "って", "った"],
+    ["ぬ", "に", "な", "ね", "の", "んで", "んだ"],
+    ["ぶ", "び", "ば", "べ", "ぼ", "んで", "んだ"],
+    ["む", "み", "ま", "め", "も", "んで", "んだ"],
+    ["る", "り", "ら", "れ", "ろ", "って", "った"],
+];
+
+lazy_static! {
+    static ref RULES: Vec<Rule> = rules();
+}
+
+fn rules() -> Vec<Rule> {
+    use WordClass as C;
+
+    let mut rules = Vec::new();
+    let mut rule = |from: &str, to: &str, class_in: WordClass, class_out: WordClass, reason: &'static str| {
+        rules.push(Rule { from: from.to_owned(), to: to.to_owned(), class_in, class_out, reason });
+    };
+
+    for [u, i, a, e, o, te, ta] in GODAN_ENDINGS {
+        let stem = |stem: &str, suffix: &str| format!("{}{}", stem, suffix);
+        rule(&stem(a, "ない"), u, C::ADJECTIVE, C::GODAN, "negative");
+        rule(ta, u, C::ANY, C::GODAN, "past");
+        rule(te, u, C::TE, C::GODAN, "te");
+        rule(&stem(ta, "ら"), u, C::ANY, C::GODAN, "-tara");
+        rule(&stem(e, "ば"), u, C::ANY, C::GODAN, "-ba");
+        rule(&stem(e, "る"), u, C::ICHIDAN, C::GODAN, "potential");
+        rule(&stem(a, "れる"), u, C::ICHIDAN, C::GODAN, "passive");
+        rule(&stem(a, "せる"), u, C::ICHIDAN, C::GODAN, "causative");
+        rule(&stem(i, "たい"), u, C::ADJECTIVE, C::GODAN, "-tai");
+        rule(&stem(i, "ます"), u, C::MASU, C::GODAN, "polite");
+        rule(&stem(o, "う"), u, C::ANY, C::GODAN, "volitional");
+        rule(e, u, C::ANY, C::GODAN, "imperative");
+    }
+    // 行く is the one godan verb in く with a te-form in って
+    for stem in ["行", "い"] {
+        rule(&format!("{}った", stem), &format!("{}く", stem), C::ANY, C::GODAN, "past");
+        rule(&format!("{}って", stem), &format!("{}く", stem), C::TE, C::GODAN, "te");
+        rule(&format!("{}ったら", stem), &format!("{}く", stem), C::ANY, C::GODAN, "-tara");
+    }
+
+    for (from, class_in, reason) in [
+        ("ない", C::ADJECTIVE, "negative"),
+        ("た", C::ANY, "past"),
+        ("て", C::TE, "te"),
+        ("たら", C::ANY, "-tara"),
+        ("れば", C::ANY, "-ba"),
+        ("られる", C::ICHIDAN, "potential or passive"),
+        ("させる", C::ICHIDAN, "causative"),
+        ("たい", C::ADJECTIVE, "-tai"),
+        ("ます", C::MASU, "polite"),
+        ("よう", C::ANY, "volitional"),
+        ("ろ", C::ANY, "imperative"),
+        ("よ", C::ANY, "imperative"),
+    ] {
+        rule(from, "る", class_in, C::ICHIDAN, reason);
+    }
+
+    for (from, class_in, reason) in [
+        ("しない", C::ADJECTIVE, "negative"),
+        ("した", C::ANY, "past"),
+        ("して", C::TE, "te"),
+        ("したら", C::ANY, "-tara"),
+        ("すれば", C::ANY, "-ba"),
+        ("できる", C::ICHIDAN, "potential"),
+        ("される", C::ICHIDAN, "passive"),
+        ("させる", C::ICHIDAN, "causative"),
+        ("したい", C::ADJECTIVE, "-tai"),
+        ("します", C::MASU, "polite"),
+        ("しよう", C::ANY, "volitional"),
+        ("しろ", C::ANY, "imperative"),
+        ("せよ", C::ANY, "imperative"),
+    ] {
+        rule(from, "する", class_in, C::SURU, reason);
+    }
+
+    // 来る is written either way, and only the kana ending changes with the kanji
+    for (kana, kanji, class_in, reason) in [
+        ("こない", "来ない", C::ADJECTIVE, "negative"),
+        ("きた", "来た", C::ANY, "past"),
+        ("きて", "来て", C::TE, "te"),
+        ("きたら", "来たら", C::ANY, "-tara"),
+        ("くれば", "来れば", C::ANY, "-ba"),
+        ("こられる", "来られる", C::ICHIDAN, "potential or passive"),
+        ("これる", "来れる", C::ICHIDAN, "potential"),
+        ("こさせる", "来させる", C::ICHIDAN, "causative"),
+        ("きたい", "来たい", C::ADJECTIVE, "-tai"),
+        ("きます", "来ます", C::MASU, "polite"),
+        ("こよう", "来よう", C::ANY, "volitional"),
+        ("こい", "来い", C::ANY, "imperative"),
+    ] {
+        rule(kana, "くる", class_in, C::KURU, reason);
+        rule(kanji, "来る", class_in, C::KURU, reason);
+    }
+
+    for (from, class_in, reason) in [
+        ("くない", C::ADJECTIVE, "negative"),
+        ("かった", C::ANY, "past"),
+        ("くて", C::TE, "te"),
+        ("かったら", C::ANY, "-tara"),
+        ("ければ", C::ANY, "-ba"),
+        ("く", C::ANY, "adverb"),
+        ("さ", C::ANY, "noun"),
+        ("すぎる", C::ICHIDAN, "-sugiru"),
+    ] {
+        rule(from, "い", class_in, C::ADJECTIVE, reason);
+    }
+
+    for (from, to, class_in, reason) in [
+        ("ている", "て", C::ICHIDAN, "te-iru"),
+        ("でいる", "で", C::ICHIDAN, "te-iru"),
+        ("てる", "て", C::ICHIDAN, "te-iru"),
+        ("でる", "で", C::ICHIDAN, "te-iru"),
+        ("てしまう", "て", C::GODAN, "-shimau"),
+        ("でしまう", "で", C::GODAN, "-shimau"),
+        ("ちゃう", "て", C::GODAN, "-chau"),
+        ("じゃう", "で", C::GODAN, "-chau"),
+        ("ちまう", "て", C::GODAN, "-chau"),
+        ("ておく", "て", C::GODAN, "-oku"),
+        ("でおく", "で", C::GODAN, "-oku"),
+        ("とく", "て", C::GODAN, "-oku"),
+        ("どく", "で", C::GODAN, "-oku"),
+    ] {
+        rule(from, to, class_in, C::TE, reason);
+    }
+
+    for (from, class_in, reason) in [
+        ("ました", C::ANY, "past"),
+        ("ません", C::ANY, "negative"),
+        ("ましょう", C::ANY, "volitional"),
+        ("まして", C::TE, "te"),
+    ] {
+        rule(from, "ます", class_in, C::MASU, reason);
+    }
+
+    rules
+}
+
+/// Every dictionary form `text` could be a conjugation of, starting with
+/// `text` itself and then by number of conjugations undone
+pub fn deinflect(text: &str) -> Vec<Deinflection> {
+    let mut results = vec![Deinflection {
+        term: text.to_owned(),
+        reasons: Vec::new(),
+        class: WordClass::ANY
+    }];
+    let mut seen: HashSet<(String, WordClass)> = HashSet::new();
+
+    let mut i = 0;
+    while i < results.len() {
+        let current = results[i].clone();
+        i += 1;
+
+        for rule in RULES.iter() {
+            if current.class != WordClass::ANY && !current.class.intersects(rule.class_in) {
+                continue;
+            }
+            let stem = match current.term.strip_suffix(rule.from.as_str()) {
+                Some(stem) => stem,
+                None => continue
+            };
+            let term = format!("{}{}", stem, rule.to);
+            if term.is_empty() || !seen.insert((term.clone(), rule.class_out)) {
+                continue;
+            }
+
+            // The te-form under an auxiliary like いる is part of that auxiliary
+            let mut reasons = current.reasons.clone();
+            if !(rule.class_in == WordClass::TE && current.class == WordClass::TE) {
+                reasons.insert(0, rule.reason.to_owned());
+            }
+            results.push(Deinflection { term, reasons, class: rule.class_out });
+        }
+    }
+
+    results
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reasons(text: &str, term: &str, class: WordClass) -> Option<Vec<String>> {
+        deinflect(text).into_iter()
+            .find(|d| d.term == term && d.class == class)
+            .map(|d| d.reasons)
+    }
+
+    #[test]
+    fn test_deinflect() {
+        assert_eq!(reasons("食べちゃう", "食べる", WordClass::ICHIDAN), Some(vec!["-chau".into()]));
+        assert_eq!(reasons("見てる", "見る", WordClass::ICHIDAN), Some(vec!["te-iru".into()]));
+        assert_eq!(reasons("書いていた", "書く", WordClass::GODAN), Some(vec!["te-iru".into(), "past".into()]));
+        assert_eq!(reasons("読めなかった", "読む", WordClass::GODAN), Some(vec!["potential".into(), "negative".into(), "past".into()]));
+        assert_eq!(reasons("来ました", "来る", WordClass::KURU), Some(vec!["polite".into(), "past".into()]));
+        assert_eq!(reasons("高くない", "高い", WordClass::ADJECTIVE), Some(vec!["negative".into()]));
+        assert_eq!(deinflect("猫")[0].term, "猫");
+    }
+
+    #[test]
+    fn test_word_class() {
+        assert!(WordClass::GODAN.matches(PartOfSpeech::Verb));
+        assert!(!WordClass::GODAN.matches(PartOfSpeech::Noun));
+        assert!(!WordClass::TE.matches(PartOfSpeech::Verb));
+        assert!(WordClass::ANY.matches(PartOfSpeech::Noun));
+    }
+}
@@ -0,0 +1,231 @@
+use serde::{Serialize, Deserialize};
+
+use crate::AnalyzerResult;
+
+/// A run of text, with its reading if it is written in kanji
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FuriganaSegment {
+    pub text: String,
+    pub reading: Option<String>
+}
+
+impl FuriganaSegment {
+    fn plain(text: &str) -> Self {
+        FuriganaSegment { text: text.to_owned(), reading: None }
+    }
+
+    fn ruby(text: &str, reading: &str) -> Self {
+        FuriganaSegment { text: text.to_owned(), reading: Some(reading.to_owned()) }
+    }
+}
+
+pub fn is_kana(c: char) -> bool {
+    matches!(c, 'ぁ'..='ゖ' | 'ゝ' | 'ゞ' | 'ァ'..='ヵ' | 'ヷ'..='ヺ' | 'ー' | 'ヽ' | 'ヾ')
+}
+
+pub fn is_kanji(c: char) -> bool {
+    matches!(c, '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' | '\u{F900}'..='\u{FAFF}' | '々')
+}
+
+pub fn katakana_to_hiragana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ァ'..='ヶ' | 'ヽ' | 'ヾ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            c => c
+        })
+        .collect()
+}
+
+pub fn hiragana_to_katakana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ぁ'..='ゖ' | 'ゝ' | 'ゞ' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+            c => c
+        })
+        .collect()
+}
+
+/// Splits `form` into kana and non-kana runs and assigns each non-kana run
+/// the part of `reading` between its neighbouring kana, so okurigana stay
+/// outside the furigana: 食べ物/たべもの gives 食(た) べ 物(もの). Only runs
+/// with a kanji get a reading, and only if it isn't the run itself.
+///
+/// If the form cannot be aligned with the reading, the whole form is
+/// annotated with the whole reading.
+pub fn align(form: &str, reading: &str) -> Vec<FuriganaSegment> {
+    try_align(form, reading).unwrap_or_else(|| vec![segment(form, &katakana_to_hiragana(reading))])
+}
+
+fn segment(text: &str, reading: &str) -> FuriganaSegment {
+    if !text.chars().any(is_kanji) || reading.is_empty() || katakana_to_hiragana(text) == reading {
+        FuriganaSegment::plain(text)
+    } else {
+        FuriganaSegment::ruby(text, reading)
+    }
+}
+
+/// Like `align`, but returns `None` if the reading doesn't fit the form
+pub fn try_align(form: &str, reading: &str) -> Option<Vec<FuriganaSegment>> {
+    let mut runs: Vec<(String, bool)> = Vec::new();
+    for c in form.chars() {
+        match runs.last_mut() {
+            Some((run, kana)) if *kana == is_kana(c) => run.push(c),
+            _ => runs.push((c.to_string(), is_kana(c)))
+        }
+    }
+
+    let reading: Vec<char> = katakana_to_hiragana(reading).chars().collect();
+    let mut readings = Vec::new();
+    if !align_runs(&runs, &reading, &mut readings) {
+        return None;
+    }
+
+    let mut kanji_readings = readings.into_iter();
+    Some(merge_plain(runs.iter()
+        .map(|(run, kana)| if *kana {
+            FuriganaSegment::plain(run)
+        } else {
+            segment(run, &kanji_readings.next().unwrap())
+        })))
+}
+
+fn align_runs(runs: &[(String, bool)], reading: &[char], out: &mut Vec<String>) -> bool {
+    match runs.split_first() {
+        None => reading.is_empty(),
+        Some(((run, true), rest)) => {
+            let run: Vec<char> = katakana_to_hiragana(run).chars().collect();
+            reading.starts_with(&run) && align_runs(rest, &reading[run.len()..], out)
+        },
+        Some(((_, false), rest)) => {
+            // Give the kanji as few kana as possible, backtracking if the rest doesn't fit
+            for end in 1..=reading.len() {
+                out.push(reading[..end].iter().collect());
+                if align_runs(rest, &reading[end..], out) {
+                    return true;
+                }
+                out.pop();
+            }
+            false
+        }
+    }
+}
+
+/// Anki "Japanese Support" syntax, e.g. `食[た]べ 物[もの]`. A space goes before
+/// every annotated run that follows other text, since Anki takes everything
+/// back to the previous space as the base of the furigana.
+pub fn to_brackets(segments: &[FuriganaSegment]) -> String {
+    let mut result = String::new();
+    for segment in segments {
+        match &segment.reading {
+            Some(reading) => {
+                if !result.is_empty() {
+                    result.push(' ');
+                }
+                result.push_str(&segment.text);
+                result.push('[');
+                result.push_str(reading);
+                result.push(']');
+            },
+            None => result.push_str(&segment.text)
+        }
+    }
+    result
+}
+
+/// HTML `<ruby>` markup, with the text itself escaped
+pub fn to_ruby(segments: &[FuriganaSegment]) -> String {
+    segments.iter()
+        .map(|segment| match &segment.reading {
+            Some(reading) => format!("<ruby>{}<rt>{}</rt></ruby>", escape_html(&segment.text), escape_html(reading)),
+            None => escape_html(&segment.text)
+        })
+        .collect()
+}
+
+/// Escapes text for use in HTML, including in quoted attribute values
+pub fn escape_html(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            c => result.push(c)
+        }
+    }
+    result
+}
+
+/// Furigana for an analyzed word, preferring a dictionary reading of the
+/// word and falling back to the IPADIC one
+pub fn headword(result: &AnalyzerResult) -> Vec<FuriganaSegment> {
+    let dict_readings = result.dict_info.iter()
+        .filter(|entry| entry.forms.contains(&result.word))
+        .flat_map(|entry| entry.readings.iter());
+
+    for reading in dict_readings {
+        if let Some(segments) = try_align(&result.word, reading) {
+            return segments;
+        }
+    }
+    align(&result.word, &result.reading)
+}
+
+/// Joins consecutive plain segments, e.g. after aligning a sentence token by token
+pub(crate) fn merge_plain(segments: impl IntoIterator<Item = FuriganaSegment>) -> Vec<FuriganaSegment> {
+    let mut merged: Vec<FuriganaSegment> = Vec::new();
+    for segment in segments {
+        match merged.last_mut() {
+            Some(last) if last.reading.is_none() && segment.reading.is_none() =>
+                last.text.push_str(&segment.text),
+            _ => merged.push(segment)
+        }
+    }
+    merged
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_align_okurigana() {
+        let segments = align("食べ物", "たべもの");
+        assert_eq!(segments, vec![
+            FuriganaSegment::ruby("食", "た"),
+            FuriganaSegment::plain("べ"),
+            FuriganaSegment::ruby("物", "もの"),
+        ]);
+        assert_eq!(to_brackets(&segments), "食[た]べ 物[もの]");
+        assert_eq!(to_ruby(&segments), "<ruby>食<rt>た</rt></ruby>べ<ruby>物<rt>もの</rt></ruby>");
+    }
+
+    #[test]
+    fn test_align_katakana_reading() {
+        assert_eq!(to_brackets(&align("お手洗い", "オテアライ")), "お 手洗[てあら]い");
+        assert_eq!(to_brackets(&align("ケーキ", "ケーキ")), "ケーキ");
+    }
+
+    #[test]
+    fn test_align_without_kanji() {
+        assert_eq!(align("ＡＢＣ", "エービーシー"), vec![FuriganaSegment::plain("ＡＢＣ")]);
+        assert_eq!(align("3つ", "みっつ"), vec![FuriganaSegment::plain("3つ")]);
+        assert_eq!(to_brackets(&align("ｶﾀｶﾅ", "カタカナ")), "ｶﾀｶﾅ");
+        assert_eq!(to_brackets(&align("Tシャツ", "ティーシャツ")), "Tシャツ");
+    }
+
+    #[test]
+    fn test_to_ruby_escapes() {
+        let segments = vec![FuriganaSegment::plain("<b class=\"x\">&"), FuriganaSegment::ruby("猫", "ねこ")];
+        assert_eq!(to_ruby(&segments), "&lt;b class=&quot;x&quot;&gt;&amp;<ruby>猫<rt>ねこ</rt></ruby>");
+    }
+
+    #[test]
+    fn test_align_mismatch() {
+        assert_eq!(try_align("食べる", "たべた"), None);
+        assert_eq!(to_brackets(&align("食べる", "たべた")), "食べる[たべた]");
+    }
+}
@@ -1,50 +1,291 @@
-use std::{collections::HashMap, io::Cursor, cmp::Reverse};
-use jp2anki_dict::{DictionaryReader, DictionaryEntry, PartOfSpeech};
+pub mod cloze;
+pub mod deinflect;
+pub mod furigana;
+pub mod options;
+pub mod ranking;
+pub mod sentence;
+pub mod stats;
+pub mod study;
+pub mod word_list;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+
+use std::{collections::{BTreeSet, HashMap, HashSet}, io::Cursor, ops::Range};
+use jp2anki_dict::{DictionaryReader, DictionaryEntry, JlptLevel, PartOfSpeech, normalize::normalize};
 use wasm_bindgen::prelude::*;
 use serde::{Serialize, Deserialize};
 use lindera::tokenizer::{Tokenizer, Token};
+use deinflect::Deinflection;
+use furigana::FuriganaSegment;
+use options::AnalysisOptions;
+use ranking::{BestSelection, Ranking};
+use sentence::Sentence;
+use stats::TextReport;
+use study::{LearningPlan, MinedSentence};
+use word_list::WordList;
 
 const IPADICT_POS: usize = 0;
+const IPADICT_POS_DETAIL: usize = 1;
+const IPADICT_CONJUGATION_TYPE: usize = 4;
 const IPADICT_BASE_FORM: usize = 6;
 const IPADICT_READING: usize = 7;
 
+/// Longest run of tokens that is checked against the dictionary as one expression
+const MAX_EXPRESSION_TOKENS: usize = 5;
+
 trait TokenExt {
     fn pos(&self) -> &str;
+    fn pos_detail(&self) -> &str;
     fn base_form(&self) -> &str;
     fn reading(&self) -> &str;
+    fn base_reading(&self) -> String;
 }
 
+// Words missing from IPADIC only have a placeholder detail, so they are
+// treated as "other" words read and looked up as written
 impl<'a> TokenExt for Token<'a> {
     fn pos(&self) -> &str {
+        if self.detail.len() <= IPADICT_READING {
+            return "その他";
+        }
         &self.detail[IPADICT_POS]
     }
+    fn pos_detail(&self) -> &str {
+        self.detail.get(IPADICT_POS_DETAIL).map_or("*", String::as_str)
+    }
     fn base_form(&self) -> &str {
-        &self.detail[IPADICT_BASE_FORM]
+        match self.detail.get(IPADICT_BASE_FORM) {
+            Some(base_form) if base_form != "*" => base_form,
+            _ => self.text
+        }
     }
     fn reading(&self) -> &str {
-        &self.detail[IPADICT_READING]
+        self.detail.get(IPADICT_READING).map_or(self.text, String::as_str)
+    }
+    /// IPADIC only has the reading of the surface form, so the reading of the
+    /// base form is derived by swapping the conjugated kana ending: 辛く/カラク
+    /// gives 辛い/カライ.
+    fn base_reading(&self) -> String {
+        let base_form = self.base_form();
+        let reading = self.reading();
+        // The kanji of 来る is read differently in each form, as in 来(キ)た
+        // and 来(コ)ない, so there is no ending to swap
+        let ka_hen = self.detail.get(IPADICT_CONJUGATION_TYPE).is_some_and(|c| c.starts_with("カ変"));
+        if let Some(stem) = base_form.strip_suffix("来る").filter(|_| ka_hen) {
+            return format!("{}クル", furigana::hiragana_to_katakana(stem));
+        }
+        let common = self.text.chars()
+            .zip(base_form.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(c, _)| c.len_utf8())
+            .sum::<usize>();
+
+        let surface_tail = furigana::hiragana_to_katakana(&self.text[common..]);
+        let base_tail = &base_form[common..];
+        match reading.strip_suffix(surface_tail.as_str()) {
+            Some(stem) if base_tail.chars().all(furigana::is_kana) =>
+                format!("{}{}", stem, furigana::hiragana_to_katakana(base_tail)),
+            _ => reading.to_owned()
+        }
     }
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct AnalyzerResult<'a> {
-    word: &'a str,
-    pos: PartOfSpeech,
-    reading: &'a str,
-    count: u32,
-    dict_info: Vec<DictionaryEntry>
+/// Moves the entries with a reading matching the word's to the front, for
+/// kana words that match the forms or readings of unrelated entries.
+/// Returns the number of matching entries.
+fn rank_by_reading(entries: &mut [DictionaryEntry], reading: &str) -> usize {
+    let reading = normalize(reading);
+    let matches = |entry: &DictionaryEntry| entry.readings.iter()
+        .any(|r| normalize(r) == reading);
+
+    // Stable, so the dictionary order is kept within both groups
+    entries.sort_by_key(|entry| !matches(entry));
+    entries.iter().take_while(|entry| matches(entry)).count()
 }
 
-impl<'a> AnalyzerResult<'a> {
-    pub fn new(tk: &'a Token<'a>) -> Self {
+/// Where a word was found in the analyzed text
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Occurrence {
+    /// The token as written, which differs from the word if it is conjugated
+    pub surface: String,
+    /// Auxiliaries conjugating the word that directly follow it, e.g. た in 食べた
+    pub inflection: String,
+    pub byte_span: Range<usize>,
+    pub char_span: Range<usize>,
+    /// Index into `AnalyzerResult::sentences`
+    pub sentence: usize,
+    /// Byte offset of the surface within the sentence
+    pub sentence_offset: usize
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct AnalyzerResult {
+    pub word: String,
+    pub pos: PartOfSpeech,
+    pub reading: String,
+    pub count: u32,
+    pub dict_info: Vec<DictionaryEntry>,
+    pub sentences: Vec<String>,
+    pub occurrences: Vec<Occurrence>,
+    /// The tokens making up an expression like 気をつける, in order
+    pub children: Vec<AnalyzerResult>,
+    /// Number of entries at the start of `dict_info` with the word's reading
+    pub reading_matches: usize,
+    /// Whether the word is in the analyzer's known words
+    pub known: bool,
+    /// Best frequency rank of the word among its entries, where 1 is the most common
+    pub frequency_rank: Option<u32>,
+    /// JMDict priority tags of the word, like news1 or nf12
+    pub priorities: Vec<String>,
+    /// Easiest JLPT level of the word among its entries
+    pub jlpt_level: Option<JlptLevel>,
+    /// How the word was conjugated, if it was only found in the dictionary
+    /// after undoing the conjugation
+    pub deinflection: Option<Deinflection>,
+    /// Set when analyzing with a `Ranking` in the options
+    pub best: Option<BestSelection>
+}
+
+/// The words of a document together with a report on the whole document
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Analysis {
+    pub words: Vec<AnalyzerResult>,
+    pub report: TextReport
+}
+
+impl AnalyzerResult {
+    pub fn new(tk: &Token<'_>) -> Self {
         AnalyzerResult {
-            word: tk.base_form(),
+            word: tk.base_form().to_owned(),
             pos: tk.pos().try_into().unwrap(),
-            reading: tk.reading(),
-            count: 0,
-            dict_info: Default::default()
+            reading: tk.base_reading(),
+            ..Default::default()
         }
     }
+
+    fn expression(word: &str, reading: &str, pos: PartOfSpeech, parts: &[Token<'_>]) -> Self {
+        AnalyzerResult {
+            word: word.to_owned(),
+            pos,
+            reading: reading.to_owned(),
+            children: parts.iter().map(AnalyzerResult::new).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// The word and, if it was deinflected, the dictionary form it was found as
+    fn lemmas(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.word.as_str())
+            .chain(self.deinflection.iter().map(|deinflection| deinflection.term.as_str()))
+    }
+
+    /// Takes the frequency rank, priority tags and JLPT level of the word
+    /// from the entries with its reading, or all entries if none have it
+    fn set_entry_info(&mut self) {
+        let entries = match self.reading_matches {
+            0 => &self.dict_info[..],
+            n => &self.dict_info[..n]
+        };
+        let word = self.lemmas().last().unwrap();
+
+        let frequency_rank = entries.iter()
+            .filter_map(|entry| entry.rank_of(word))
+            .min();
+        let mut priorities: Vec<String> = Vec::new();
+        for tag in entries.iter().filter_map(|entry| entry.priorities.get(word)).flatten() {
+            if !priorities.contains(tag) {
+                priorities.push(tag.clone());
+            }
+        }
+
+        self.frequency_rank = frequency_rank;
+        self.priorities = priorities;
+        self.jlpt_level = entries.iter().filter_map(|entry| entry.jlpt_level).min();
+    }
+
+    // Occurrences are added in text order, so an occurrence is either in the
+    // last sentence or in a new one
+    fn add_occurrence(&mut self, mut occurrence: Occurrence, sentence: &str) {
+        if self.sentences.last().map(String::as_str) != Some(sentence) {
+            self.sentences.push(sentence.to_owned());
+        }
+        occurrence.sentence = self.sentences.len() - 1;
+        self.occurrences.push(occurrence);
+    }
+}
+
+/// Whether `token` can be the first token of a multi-token expression
+fn starts_expression(token: &Token<'_>) -> bool {
+    !matches!(token.pos(), "助詞" | "助動詞" | "記号")
+}
+
+/// The part of speech of `entry` as an expression written `word` that ends
+/// in `last`. The entry must be written that way, not just read that way,
+/// and be an expression or take the part of speech of `last`, so that e.g.
+/// いる+か isn't taken for いるか (海豚) and する+と isn't taken for すると.
+fn expression_pos(entry: &DictionaryEntry, word: &str, last: &Token<'_>) -> Option<PartOfSpeech> {
+    let key = normalize(word);
+    let written_as = |words: &[String]| words.iter().any(|w| normalize(w) == key);
+    let kana_headword = entry.forms.is_empty() || entry.usually_kana();
+    let headword = written_as(&entry.forms) || (kana_headword && written_as(&entry.readings));
+    if !headword {
+        return None;
+    }
+
+    let entry_pos: BTreeSet<PartOfSpeech> = entry.definitions.iter()
+        .flat_map(|definition| definition.pos.iter().copied())
+        .collect();
+    // The last token carries the inflection, e.g. the verb in 気をつける
+    let last_pos = last.pos().try_into().unwrap();
+    if entry_pos.contains(&last_pos) {
+        Some(last_pos)
+    } else if entry_pos.contains(&PartOfSpeech::Other) {
+        // Expressions (&exp;) are listed together with what they act as
+        entry_pos.iter().copied().find(|pos| *pos != PartOfSpeech::Other).or(Some(PartOfSpeech::Other))
+    } else {
+        None
+    }
+}
+
+/// Byte and char ranges of every token in `text`
+fn token_spans(text: &str, tokens: &[Token<'_>]) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut byte_pos = 0;
+    let mut char_pos = 0;
+    tokens.iter()
+        .map(|token| {
+            // Tokens appear in order, so each one is found after the previous one
+            let start = text[byte_pos..].find(token.text)
+                .map_or(byte_pos, |i| byte_pos + i);
+            let end = start + token.text.len();
+            let char_start = char_pos + text[byte_pos..start].chars().count();
+            let char_end = char_start + token.text.chars().count();
+            byte_pos = end;
+            char_pos = char_end;
+            (start..end, char_start..char_end)
+        })
+        .collect()
+}
+
+/// Whether `next` conjugates a verb or adjective, for tokens directly following it
+fn is_inflection(word: &Token<'_>, next: &Token<'_>) -> bool {
+    if !matches!(word.pos(), "動詞" | "形容詞") {
+        return false;
+    }
+    match (next.pos(), next.pos_detail()) {
+        ("助動詞", _) => true,
+        ("動詞", "接尾") | ("動詞", "非自立") => true,
+        ("助詞", "接続助詞") => matches!(next.text, "て" | "で" | "ちゃ" | "じゃ"),
+        _ => false
+    }
+}
+
+// Options are optional in JS, so `undefined` and `null` give the defaults
+fn options_from_js(options: JsValue) -> AnalysisOptions {
+    if options.is_undefined() || options.is_null() {
+        AnalysisOptions::default()
+    } else {
+        options.into_serde().unwrap()
+    }
 }
 
 #[wasm_bindgen]
@@ -52,9 +293,17 @@ pub fn init() {
     console_error_panic_hook::set_once();
 }
 
+// Base form, reading and part of speech of a word
+type WordKey = (String, String, PartOfSpeech);
+
 #[wasm_bindgen]
 pub struct TextAnalyzer {
     dictionary: DictionaryReader<Cursor<Vec<u8>>>,
+    // Building the tokenizer loads the whole IPADIC, so it is only done once
+    tokenizer: Tokenizer,
+    known_words: WordList,
+    // Names, fillers and other words that are left out of the results
+    ignored_words: WordList,
 }
 
 #[wasm_bindgen]
@@ -65,30 +314,456 @@ impl TextAnalyzer {
             dictionary: DictionaryReader::new(
                 Cursor::new(idx_file),
                 Cursor::new(dat_file)
-            ).unwrap()
+            ).unwrap_or_else(|err| panic!("Could not load the dictionary: {}", err)),
+            tokenizer: Tokenizer::new().unwrap(),
+            known_words: WordList::new(),
+            ignored_words: WordList::new()
         }
     }
 
     #[wasm_bindgen]
     pub fn analyze(&mut self, text: &str) -> JsValue {
-        let tokenizer = Tokenizer::new().unwrap();
-        let tokens: Vec<Token<'_>> = tokenizer.tokenize(text).unwrap();
-    
-        let mut words: HashMap<&str, AnalyzerResult<'_>> = HashMap::new();
-        for token in &tokens {
-            let entry = words.entry(token.base_form())
-                .or_insert_with(|| AnalyzerResult::new(token));
+        JsValue::from_serde(&self.analyze_text(text)).unwrap()
+    }
+
+    /// Like `analyze`, with an `AnalysisOptions` object
+    #[wasm_bindgen]
+    pub fn analyze_with_options(&mut self, text: &str, options: JsValue) -> JsValue {
+        let options = options_from_js(options);
+        JsValue::from_serde(&self.analyze_texts(&[text], &options).pop().unwrap()).unwrap()
+    }
+
+    /// Like `analyze_with_options`, returning an `Analysis` with a `TextReport`
+    #[wasm_bindgen]
+    pub fn analyze_with_report(&mut self, text: &str, options: JsValue) -> JsValue {
+        let options = options_from_js(options);
+        JsValue::from_serde(&self.analyze_with_reports(&[text], &options).pop().unwrap()).unwrap()
+    }
+
+    /// Analyzes an array of strings, returning an array of results per string
+    #[wasm_bindgen]
+    pub fn analyze_documents(&mut self, texts: JsValue, options: JsValue) -> JsValue {
+        let texts: Vec<String> = texts.into_serde().unwrap();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let options = options_from_js(options);
+        JsValue::from_serde(&self.analyze_texts(&texts, &options)).unwrap()
+    }
+
+    /// Orders the unknown words of an array of strings by how soon learning
+    /// them makes their sentences comprehensible, see `study::learning_plan`
+    #[wasm_bindgen]
+    pub fn learning_plan(&mut self, texts: JsValue, max_words: Option<usize>) -> JsValue {
+        let texts: Vec<String> = texts.into_serde().unwrap();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        JsValue::from_serde(&self.plan_learning(&texts, max_words)).unwrap()
+    }
+
+    /// Finds the sentences of a text with exactly one unknown word, paired
+    /// with its best entry under an optional `Ranking` object
+    #[wasm_bindgen]
+    pub fn mine_sentences(&mut self, text: &str, ranking: JsValue) -> JsValue {
+        let ranking = match ranking.is_undefined() || ranking.is_null() {
+            true => Ranking::default(),
+            false => ranking.into_serde().unwrap()
+        };
+        JsValue::from_serde(&self.mine_texts(&[text], &ranking).pop().unwrap()).unwrap()
+    }
+
+    #[wasm_bindgen]
+    pub fn add_known(&mut self, word: &str) -> bool {
+        self.known_words.insert(word)
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_known(&mut self, word: &str) -> bool {
+        self.known_words.remove(word)
+    }
+
+    /// Adds a list of words with one word per line, see `WordList::from_text`
+    #[wasm_bindgen]
+    pub fn import_known(&mut self, text: &str) -> usize {
+        self.known_words.import(text)
+    }
+
+    #[wasm_bindgen]
+    pub fn export_known(&self) -> String {
+        self.known_words.to_text()
+    }
+
+    #[wasm_bindgen]
+    pub fn add_ignored(&mut self, word: &str) -> bool {
+        self.ignored_words.insert(word)
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_ignored(&mut self, word: &str) -> bool {
+        self.ignored_words.remove(word)
+    }
+
+    #[wasm_bindgen]
+    pub fn import_ignored(&mut self, text: &str) -> usize {
+        self.ignored_words.import(text)
+    }
+
+    #[wasm_bindgen]
+    pub fn export_ignored(&self) -> String {
+        self.ignored_words.to_text()
+    }
+}
+
+impl TextAnalyzer {
+    pub fn analyze_text(&mut self, text: &str) -> Vec<AnalyzerResult> {
+        self.analyze_texts(&[text], &AnalysisOptions::default()).pop().unwrap()
+    }
+
+    /// Analyzes every document separately, but with a single dictionary
+    /// lookup for the words of all of them
+    pub fn analyze_texts(&mut self, texts: &[&str], options: &AnalysisOptions) -> Vec<Vec<AnalyzerResult>> {
+        self.analyze_with_reports(texts, options).into_iter()
+            .map(|analysis| analysis.words)
+            .collect()
+    }
+
+    /// Like `analyze_texts`, with a report on each document. Reports cover
+    /// every word except ignored ones, regardless of the options' filters.
+    pub fn analyze_with_reports(&mut self, texts: &[&str], options: &AnalysisOptions) -> Vec<Analysis> {
+        let mut documents: Vec<Vec<AnalyzerResult>> = texts.iter()
+            .map(|text| self.collect_words(text).into_values().collect())
+            .collect();
+
+        let all_words: Vec<&str> = documents.iter()
+            .flatten()
+            .flat_map(|result| std::iter::once(result).chain(&result.children))
+            .map(|result| result.word.as_str())
+            .collect::<HashSet<&str>>()
+            .into_iter()
+            .collect();
+        let dict_info: HashMap<String, Vec<DictionaryEntry>> = self.dictionary.lookup(&all_words).unwrap()
+            .into_iter()
+            .map(|(word, entries)| (word.to_owned(), entries))
+            .collect();
+
+        let add_dict_info = |result: &mut AnalyzerResult| {
+            if let Some(entries) = dict_info.get(&result.word) {
+                result.dict_info = entries.clone();
+                result.reading_matches = rank_by_reading(&mut result.dict_info, &result.reading);
+            }
+        };
+        for result in documents.iter_mut().flatten() {
+            add_dict_info(result);
+            result.children.iter_mut().for_each(&add_dict_info);
+        }
+
+        let unmatched: Vec<&mut AnalyzerResult> = documents.iter_mut()
+            .flatten()
+            .filter(|result| result.dict_info.is_empty() && result.pos != PartOfSpeech::Symbol)
+            .collect();
+        self.add_deinflections(unmatched);
+
+        documents.into_iter()
+            .map(|mut words| {
+                words.retain(|result| !result.lemmas().any(|word| self.ignored_words.contains(word)));
+                for result in words.iter_mut() {
+                    let known = result.lemmas().any(|word| self.known_words.contains(word));
+                    result.known = known;
+                    result.set_entry_info();
+                    for child in &mut result.children {
+                        child.known = self.known_words.contains(&child.word);
+                    }
+                }
+                let report = TextReport::new(&words, &self.known_words);
+                options.apply(&mut words);
+                Analysis { words, report }
+            })
+            .collect()
+    }
+
+    pub fn plan_learning(&mut self, texts: &[&str], max_words: Option<usize>) -> LearningPlan {
+        let documents = self.analyze_texts(texts, &AnalysisOptions::default());
+        study::learning_plan(&documents, max_words)
+    }
+
+    /// The i+1 sentences of every document, see `study::mine_sentences`
+    pub fn mine_texts(&mut self, texts: &[&str], ranking: &Ranking) -> Vec<Vec<MinedSentence>> {
+        self.analyze_texts(texts, &AnalysisOptions::default()).iter()
+            .map(|results| study::mine_sentences(results, ranking))
+            .collect()
+    }
+
+    /// Looks up words the dictionary doesn't have as tokenized, like the
+    /// colloquial 食べちゃう, by the dictionary forms they could be conjugations of
+    fn add_deinflections(&mut self, results: Vec<&mut AnalyzerResult>) {
+        let candidates: Vec<Vec<Deinflection>> = results.iter()
+            .map(|result| {
+                let mut forms = vec![result.word.clone()];
+                forms.extend(result.occurrences.first()
+                    .map(|occurrence| format!("{}{}", occurrence.surface, occurrence.inflection)));
+                forms.iter()
+                    .flat_map(|form| deinflect::deinflect(form))
+                    .filter(|candidate| !(candidate.reasons.is_empty() && candidate.term == result.word))
+                    .collect()
+            })
+            .collect();
+
+        let terms: Vec<&str> = candidates.iter()
+            .flatten()
+            .map(|candidate| candidate.term.as_str())
+            .collect::<HashSet<&str>>()
+            .into_iter()
+            .collect();
+        let entries = self.dictionary.lookup(&terms).unwrap();
+
+        for (result, candidates) in results.into_iter().zip(&candidates) {
+            let found = candidates.iter().find_map(|candidate| {
+                let matching: Vec<DictionaryEntry> = entries.get(candidate.term.as_str())?
+                    .iter()
+                    .filter(|entry| candidate.matches_entry(entry))
+                    .cloned()
+                    .collect();
+                (!matching.is_empty()).then_some((candidate, matching))
+            });
+            if let Some((candidate, matching)) = found {
+                result.dict_info = matching;
+                result.deinflection = Some(candidate.clone());
+            }
+        }
+    }
+
+    pub fn known_words(&mut self) -> &mut WordList {
+        &mut self.known_words
+    }
+
+    pub fn ignored_words(&mut self) -> &mut WordList {
+        &mut self.ignored_words
+    }
+
+    fn collect_words(&mut self, text: &str) -> HashMap<WordKey, AnalyzerResult> {
+        // Homographs like 今日 (きょう/こんにち) are kept apart by their reading and part of speech
+        let mut words: HashMap<WordKey, AnalyzerResult> = HashMap::new();
+        for sentence in sentence::split_sentences(text) {
+            self.collect_sentence_words(text, &sentence, &mut words);
+        }
+        words
+    }
+
+    // Sentences are tokenized separately, so expressions and inflections
+    // never reach into the next sentence
+    fn collect_sentence_words(&mut self, text: &str, sentence_span: &Sentence, words: &mut HashMap<WordKey, AnalyzerResult>) {
+        let sentence = sentence_span.text(text);
+        let sentence_start = sentence_span.byte_span.start;
+        let tokens: Vec<Token<'_>> = self.tokenizer.tokenize(sentence).unwrap();
+        let spans: Vec<(Range<usize>, Range<usize>)> = token_spans(sentence, &tokens).into_iter()
+            .map(|(byte_span, char_span)| (
+                sentence_start + byte_span.start..sentence_start + byte_span.end,
+                sentence_span.char_span.start + char_span.start..sentence_span.char_span.start + char_span.end
+            ))
+            .collect();
+
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            let expression = self.find_expression(&tokens[i..]);
+            let len = expression.as_ref().map_or(1, |(len, _, _, _)| *len);
+            let parts = &tokens[i..i + len];
+            let part_spans = &spans[i..i + len];
+            let last = &parts[len - 1];
+
+            let byte_span = part_spans[0].0.start..part_spans[len - 1].0.end;
+            let char_span = part_spans[0].1.start..part_spans[len - 1].1.end;
+
+            let inflection_end = tokens.iter()
+                .zip(&spans)
+                .skip(i + len)
+                .take_while(|(next, _)| is_inflection(last, next))
+                .last()
+                .map_or(byte_span.end, |(_, (span, _))| span.end);
+            let inflection = &text[byte_span.end..inflection_end];
+
+            let occurrence = Occurrence {
+                surface: parts.iter().map(|part| part.text).collect(),
+                inflection: inflection.to_owned(),
+                sentence: 0,
+                sentence_offset: byte_span.start - sentence_start,
+                byte_span,
+                char_span
+            };
+
+            let entry = match &expression {
+                Some((_, word, reading, pos)) => words
+                    .entry((word.clone(), reading.clone(), *pos))
+                    .or_insert_with(|| AnalyzerResult::expression(word, reading, *pos, parts)),
+                None => words
+                    .entry((token.base_form().to_owned(), token.base_reading(), token.pos().try_into().unwrap()))
+                    .or_insert_with(|| AnalyzerResult::new(token))
+            };
             entry.count += 1;
+            entry.add_occurrence(occurrence, sentence);
+
+            for (j, (child, (part, (byte_span, char_span)))) in entry.children.iter_mut().zip(parts.iter().zip(part_spans)).enumerate() {
+                child.count += 1;
+                child.add_occurrence(Occurrence {
+                    surface: part.text.to_owned(),
+                    inflection: if j + 1 == len { inflection.to_owned() } else { String::new() },
+                    sentence: 0,
+                    sentence_offset: byte_span.start - sentence_start,
+                    byte_span: byte_span.clone(),
+                    char_span: char_span.clone()
+                }, sentence);
+            }
+
+            i += len;
         }
-    
-        let all_words: Vec<&str> = words.keys().copied().collect();
-        for (word, entries) in self.dictionary.lookup(&all_words).unwrap() {
-            words.get_mut(word).unwrap().dict_info = entries;
+    }
+
+    /// The longest run of at least two tokens at the start of `tokens` that
+    /// is a dictionary headword, as its length, form, reading and part of
+    /// speech. The last token may be conjugated, so its base form is tried
+    /// before its surface.
+    fn find_expression(&mut self, tokens: &[Token<'_>]) -> Option<(usize, String, String, PartOfSpeech)> {
+        if !tokens.first().is_some_and(starts_expression) {
+            return None;
         }
+        let max_len = tokens.iter()
+            .take(MAX_EXPRESSION_TOKENS)
+            .take_while(|token| token.pos() != "記号")
+            .count();
+
+        for len in (2..=max_len).rev() {
+            let (last, init) = tokens[..len].split_last().unwrap();
+            let stem: String = init.iter().map(|token| token.text).collect();
+            let stem_reading: String = init.iter().map(|token| token.reading()).collect();
+
+            for (tail, tail_reading) in [(last.base_form(), last.base_reading()), (last.text, last.reading().to_owned())] {
+                let word = format!("{}{}", stem, tail);
+                // Checking the index first saves reading entries for most runs
+                if !self.dictionary.contains(&word) {
+                    continue;
+                }
+                let pos = self.dictionary.lookup(&[&word]).unwrap()
+                    .values()
+                    .flatten()
+                    .find_map(|entry| expression_pos(entry, &word, last));
+                if let Some(pos) = pos {
+                    return Some((len, word, format!("{}{}", stem_reading, tail_reading), pos));
+                }
+            }
+        }
+        None
+    }
 
-        let mut words: Vec<AnalyzerResult<'_>> = words.into_values().collect();
-        words.sort_by_key(|res| Reverse(res.count));
+    /// Furigana for running text such as example sentences, using the IPADIC
+    /// reading of every token
+    pub fn furigana(&self, text: &str) -> Vec<FuriganaSegment> {
+        let tokens: Vec<Token<'_>> = self.tokenizer.tokenize(text).unwrap();
+
+        furigana::merge_plain(tokens.iter().flat_map(|token| {
+            match token.detail.get(IPADICT_READING) {
+                Some(reading) if reading != "*" => furigana::align(token.text, reading),
+                _ => vec![FuriganaSegment { text: token.text.to_owned(), reading: None }]
+            }
+        }))
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use jp2anki_dict::{Definition, DictionaryWriter, Source};
+
+    use super::*;
+    use crate::test_util::{entry, occurrence};
+
+    fn token<'a>(text: &'a str, pos: &str, base_form: &str, reading: &str) -> Token<'a> {
+        let detail = [pos, "自立", "*", "*", "*", "*", base_form, reading, reading]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        Token { text, detail }
+    }
+
+    #[test]
+    fn test_base_reading() {
+        assert_eq!(token("辛く", "形容詞", "辛い", "カラク").base_reading(), "カライ");
+        assert_eq!(token("食べ", "動詞", "食べる", "タベ").base_reading(), "タベル");
+        assert_eq!(token("し", "動詞", "する", "シ").base_reading(), "スル");
+        assert_eq!(token("今日", "名詞", "今日", "キョウ").base_reading(), "キョウ");
+
+        let kuru = |reading| {
+            let mut token = token("来", "動詞", "来る", reading);
+            token.detail[IPADICT_CONJUGATION_TYPE] = "カ変・来ル".into();
+            token.base_reading()
+        };
+        assert_eq!(kuru("キ"), "クル");
+        assert_eq!(kuru("コ"), "クル");
+        assert_eq!(token("き", "動詞", "くる", "キ").base_reading(), "クル");
+    }
+
+    #[test]
+    fn test_find_expression() {
+        let mut dat_file = Vec::new();
+        let mut idx_file = Vec::new();
+        let mut dict_w = DictionaryWriter::new(&mut dat_file);
+        let definition = |pos: &[&str], flags: &[&str]| Definition::new(
+            String::new(),
+            pos.iter().map(|pos| pos.to_string()).collect(),
+            flags.iter().map(|flag| flag.to_string()).collect()
+        ).unwrap();
+        dict_w.add(DictionaryEntry {
+            definitions: vec![definition(&["&exp;", "&v1;"], &[])],
+            ..entry(Source::JMDict(1), &["気を付ける", "気をつける"], &["きをつける"])
+        }).unwrap();
+        dict_w.add(DictionaryEntry {
+            definitions: vec![definition(&["&n;"], &["&uk;"])],
+            ..entry(Source::JMDict(2), &["海豚"], &["いるか"])
+        }).unwrap();
+        dict_w.add(DictionaryEntry {
+            definitions: vec![definition(&["&conj;"], &[])],
+            ..entry(Source::JMDict(3), &[], &["すると"])
+        }).unwrap();
+        dict_w.finish(&mut idx_file).unwrap();
+        let mut analyzer = TextAnalyzer::new(idx_file, dat_file);
+
+        let tokens = vec![
+            token("気", "名詞", "気", "キ"),
+            token("を", "助詞", "を", "ヲ"),
+            token("つけ", "動詞", "つける", "ツケ"),
+            token("て", "助詞", "て", "テ"),
+        ];
+        assert_eq!(
+            analyzer.find_expression(&tokens),
+            Some((3, "気をつける".to_owned(), "キヲツケル".to_owned(), PartOfSpeech::Verb))
+        );
+        assert_eq!(analyzer.find_expression(&tokens[1..]), None);
+
+        // Found in the dictionary, but as words of another part of speech
+        let iruka = vec![token("いる", "動詞", "いる", "イル"), token("か", "助詞", "か", "カ")];
+        assert_eq!(analyzer.find_expression(&iruka), None);
+        let suruto = vec![token("する", "動詞", "する", "スル"), token("と", "助詞", "と", "ト")];
+        assert_eq!(analyzer.find_expression(&suruto), None);
+    }
+
+    #[test]
+    fn test_add_occurrence() {
+        let mut neko = crate::test_util::result("猫", "ネコ");
+        neko.add_occurrence(occurrence("猫", 0, 0, 0), "猫と猫。");
+        neko.add_occurrence(occurrence("猫", 0, 0, "猫と".len()), "猫と猫。");
+        neko.add_occurrence(occurrence("猫", 0, "猫と猫。".len(), 0), "猫だ。");
+        assert_eq!(neko.sentences, vec!["猫と猫。", "猫だ。"]);
+        let sentences: Vec<usize> = neko.occurrences.iter().map(|occurrence| occurrence.sentence).collect();
+        assert_eq!(sentences, vec![0, 0, 1]);
+    }
 
-        JsValue::from_serde(&words).unwrap()
+    #[test]
+    fn test_rank_by_reading() {
+        let mut entries = vec![
+            entry(Source::JMDict(1), &[], &["こんにち"]),
+            entry(Source::JMDict(2), &[], &["きょう"]),
+            entry(Source::JMDict(3), &[], &["けふ"]),
+            entry(Source::JMDict(4), &[], &["キョウ"])
+        ];
+        assert_eq!(rank_by_reading(&mut entries, "キョウ"), 2);
+        let sources: Vec<Source> = entries.iter().map(|entry| entry.source).collect();
+        assert_eq!(sources, vec![Source::JMDict(2), Source::JMDict(4), Source::JMDict(1), Source::JMDict(3)]);
     }
 }
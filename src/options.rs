@@ -0,0 +1,175 @@
+use std::{cmp::Reverse, collections::HashSet};
+use jp2anki_dict::PartOfSpeech;
+use serde::{Serialize, Deserialize};
+
+use crate::{AnalyzerResult, ranking::Ranking};
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// Most frequent in the text first
+    #[default]
+    Count,
+    /// In the order the words first appear in the text
+    FirstAppearance,
+    /// Most common in the language first, with unranked words last. Ranks
+    /// come from the dictionary, which has them from JMDict priority tags or
+    /// the builder's `--frequency-list`; without any, this sorts like `Count`.
+    FrequencyRank,
+    /// Easiest JLPT level first, with words without a level last. Levels are
+    /// only in dictionaries built with `--jlpt-list`; without them, this
+    /// sorts like `Count`.
+    JlptLevel
+}
+
+/// Which analyzed words to return and in what order. Fields missing when
+/// deserializing keep their default, which returns every word.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct AnalysisOptions {
+    pub exclude_pos: HashSet<PartOfSpeech>,
+    pub exclude_known: bool,
+    pub min_count: u32,
+    /// Leaves out words ranked less common than this, and dictionary words
+    /// without any rank
+    pub max_frequency_rank: Option<u32>,
+    pub max_results: Option<usize>,
+    pub sort_by: SortKey,
+    /// Selects the best definitions, audio and readings of every result
+    pub ranking: Option<Ranking>
+}
+
+impl AnalysisOptions {
+    /// Filters, sorts and truncates the results of one document
+    pub fn apply(&self, results: &mut Vec<AnalyzerResult>) {
+        results.retain(|result| {
+            let excluded = self.exclude_pos.contains(&result.pos) || (self.exclude_known && result.known);
+            let obscure = self.max_frequency_rank.is_some_and(|max_rank| match result.frequency_rank {
+                Some(rank) => rank > max_rank,
+                None => !result.dict_info.is_empty()
+            });
+            !excluded && !obscure && result.count >= self.min_count
+        });
+
+        let first_appearance = |result: &AnalyzerResult| result.occurrences.first()
+            .map_or(usize::MAX, |occurrence| occurrence.byte_span.start);
+        match self.sort_by {
+            SortKey::Count => results.sort_by_key(|result| (Reverse(result.count), first_appearance(result))),
+            SortKey::FirstAppearance => results.sort_by_key(first_appearance),
+            SortKey::FrequencyRank => results.sort_by_key(|result| (
+                result.frequency_rank.unwrap_or(u32::MAX),
+                Reverse(result.count),
+                first_appearance(result)
+            )),
+            SortKey::JlptLevel => results.sort_by_key(|result| (
+                result.jlpt_level.is_none(),
+                result.jlpt_level,
+                Reverse(result.count),
+                first_appearance(result)
+            ))
+        }
+
+        if let Some(max_results) = self.max_results {
+            results.truncate(max_results);
+        }
+
+        if let Some(ranking) = &self.ranking {
+            for result in results.iter_mut() {
+                result.best = Some(ranking.best(result));
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use jp2anki_dict::JlptLevel;
+
+    use crate::test_util::occurrence;
+
+    use super::*;
+
+    fn result(word: &str, pos: PartOfSpeech, count: u32, first: usize) -> AnalyzerResult {
+        AnalyzerResult {
+            pos,
+            count,
+            known: word == "犬",
+            frequency_rank: Some(count * 1000),
+            occurrences: vec![occurrence(word, 0, 0, first)],
+            ..crate::test_util::result(word, word)
+        }
+    }
+
+    fn words(results: &[AnalyzerResult]) -> Vec<&str> {
+        results.iter().map(|result| result.word.as_str()).collect()
+    }
+
+    #[test]
+    fn test_apply() {
+        let results = vec![
+            result("が", PartOfSpeech::Particle, 5, 3),
+            result("鳥", PartOfSpeech::Noun, 1, 0),
+            result("猫", PartOfSpeech::Noun, 2, 9),
+            result("犬", PartOfSpeech::Noun, 2, 6),
+        ];
+
+        let mut sorted = results.clone();
+        AnalysisOptions::default().apply(&mut sorted);
+        assert_eq!(words(&sorted), vec!["が", "犬", "猫", "鳥"]);
+
+        let options = AnalysisOptions {
+            exclude_pos: [PartOfSpeech::Particle].into_iter().collect(),
+            exclude_known: true,
+            max_results: Some(1),
+            sort_by: SortKey::FirstAppearance,
+            ..Default::default()
+        };
+        let mut filtered = results.clone();
+        options.apply(&mut filtered);
+        assert_eq!(words(&filtered), vec!["鳥"]);
+
+        let mut frequent = results.clone();
+        AnalysisOptions { min_count: 2, ..Default::default() }.apply(&mut frequent);
+        assert_eq!(words(&frequent), vec!["が", "犬", "猫"]);
+
+        let mut common = results;
+        let options = AnalysisOptions {
+            max_frequency_rank: Some(2000),
+            sort_by: SortKey::FrequencyRank,
+            ..Default::default()
+        };
+        options.apply(&mut common);
+        assert_eq!(words(&common), vec!["鳥", "犬", "猫"]);
+    }
+
+    #[test]
+    fn test_sort_unranked() {
+        let mut results = vec![
+            result("鳥", PartOfSpeech::Noun, 1, 0),
+            result("猫", PartOfSpeech::Noun, 2, 9),
+            result("犬", PartOfSpeech::Noun, 3, 6),
+        ];
+        results[0].jlpt_level = Some(JlptLevel::N3);
+        results[1].frequency_rank = None;
+
+        let mut by_rank = results.clone();
+        AnalysisOptions { sort_by: SortKey::FrequencyRank, ..Default::default() }.apply(&mut by_rank);
+        assert_eq!(words(&by_rank), vec!["鳥", "犬", "猫"]);
+
+        let mut by_level = results.clone();
+        AnalysisOptions { sort_by: SortKey::JlptLevel, ..Default::default() }.apply(&mut by_level);
+        assert_eq!(words(&by_level), vec!["鳥", "犬", "猫"]);
+
+        // Without any ranks or levels both fall back to the count
+        for result in &mut results {
+            result.frequency_rank = None;
+            result.jlpt_level = None;
+        }
+        for sort_by in [SortKey::FrequencyRank, SortKey::JlptLevel] {
+            let mut sorted = results.clone();
+            AnalysisOptions { sort_by, ..Default::default() }.apply(&mut sorted);
+            assert_eq!(words(&sorted), vec!["犬", "猫", "鳥"]);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use jp2anki_dict::{Definition, DictionaryEntry, Example, PartOfSpeech, Source, normalize::normalize};
+use serde::{Serialize, Deserialize};
+
+use crate::{AnalyzerResult, furigana};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    WaniKani,
+    JMDict
+}
+
+/// Condition on a definition, the entry it belongs to and the analyzed word
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Predicate {
+    /// The definition has a flag such as `&arch;` or `&uk;`
+    Flag(String),
+    Pos(PartOfSpeech),
+    /// The definition has the part of speech of the analyzed word
+    WordPos,
+    Source(SourceKind),
+    /// The analyzed word is written in kana only
+    KanaWord,
+    /// The entry has the reading of the analyzed word
+    WordReading,
+    /// The entry has the analyzed word as a form
+    WordForm,
+    Not(Box<Predicate>),
+    All(Vec<Predicate>),
+    Any(Vec<Predicate>),
+    /// Both predicates hold or neither does
+    Equal(Box<Predicate>, Box<Predicate>)
+}
+
+impl Predicate {
+    pub fn matches(&self, definition: &Definition, entry: &DictionaryEntry, word: &AnalyzerResult) -> bool {
+        match self {
+            Predicate::Flag(flag) => definition.flags.contains(flag),
+            Predicate::Pos(pos) => definition.pos.contains(pos),
+            Predicate::WordPos => definition.pos.contains(&word.pos),
+            Predicate::Source(kind) => matches!(
+                (kind, entry.source),
+                (SourceKind::WaniKani, Source::WaniKani(_)) | (SourceKind::JMDict, Source::JMDict(_))
+            ),
+            Predicate::KanaWord => word.word.chars().all(furigana::is_kana),
+            Predicate::WordReading => {
+                let reading = normalize(&word.reading);
+                entry.readings.iter().any(|r| normalize(r) == reading)
+            },
+            Predicate::WordForm => entry.forms.contains(&word.word),
+            Predicate::Not(predicate) => !predicate.matches(definition, entry, word),
+            Predicate::All(predicates) => predicates.iter().all(|p| p.matches(definition, entry, word)),
+            Predicate::Any(predicates) => predicates.iter().any(|p| p.matches(definition, entry, word)),
+            Predicate::Equal(a, b) => a.matches(definition, entry, word) == b.matches(definition, entry, word)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RankingRule {
+    pub predicate: Predicate,
+    pub weight: i32
+}
+
+/// Tiers of rules weighting dictionary entries. An entry gets the weight of
+/// every rule that one of its definitions matches, and entries are compared
+/// by the weights of each tier in turn, so later tiers only break ties.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Ranking {
+    pub tiers: Vec<Vec<RankingRule>>
+}
+
+/// The definitions, audio and readings of the best ranked entries having any
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct BestSelection {
+    pub definitions: Vec<Definition>,
+    pub examples: Vec<Example>,
+    pub audio: Vec<String>,
+    pub readings: Vec<String>
+}
+
+impl Ranking {
+    pub fn weight(&self, entry: &DictionaryEntry, word: &AnalyzerResult) -> Vec<i32> {
+        self.tiers.iter()
+            .map(|rules| rules.iter()
+                .filter(|rule| entry.definitions.iter()
+                    .any(|definition| rule.predicate.matches(definition, entry, word)))
+                .map(|rule| rule.weight)
+                .sum())
+            .collect()
+    }
+
+    /// Entries from best to worst, keeping the dictionary order for equal weights
+    pub fn rank<'a>(&self, word: &'a AnalyzerResult) -> Vec<(Vec<i32>, &'a DictionaryEntry)> {
+        let mut entries: Vec<(Vec<i32>, &DictionaryEntry)> = word.dict_info.iter()
+            .map(|entry| (self.weight(entry, word), entry))
+            .collect();
+        entries.sort_by(|a, b| b.0.cmp(&a.0));
+        entries
+    }
+
+    /// Takes the definitions (with their examples), audio and readings each
+    /// from the highest weighted group of equally ranked entries that has any
+    pub fn best(&self, word: &AnalyzerResult) -> BestSelection {
+        let entries = self.rank(word);
+        let mut best = BestSelection::default();
+
+        for group in group_by_weight(&entries) {
+            if best.definitions.is_empty() {
+                for (_, entry) in group {
+                    best.definitions.extend(entry.definitions.iter().cloned());
+                    best.examples.extend(entry.examples.iter().cloned());
+                }
+            }
+            if best.audio.is_empty() {
+                best.audio.extend(group.iter().flat_map(|(_, entry)| entry.audio.iter().cloned()));
+            }
+            if best.readings.is_empty() {
+                for reading in group.iter().flat_map(|(_, entry)| &entry.readings) {
+                    if !best.readings.contains(reading) {
+                        best.readings.push(reading.clone());
+                    }
+                }
+            }
+        }
+
+        // IPADIC's reading is the fallback for words missing from the dictionary
+        if best.readings.is_empty() {
+            best.readings.push(word.reading.clone());
+        }
+        // A kana word's own spelling beats the readings of other spellings,
+        // e.g. the entry for そした also lists しかした
+        if best.readings.contains(&word.word) {
+            best.readings = vec![word.word.clone()];
+        }
+
+        best
+    }
+}
+
+fn group_by_weight<'e, 'a>(entries: &'e [(Vec<i32>, &'a DictionaryEntry)]) -> impl Iterator<Item = &'e [(Vec<i32>, &'a DictionaryEntry)]> {
+    let mut rest = entries;
+    std::iter::from_fn(move || {
+        let (weight, _) = rest.first()?;
+        let len = rest.iter().take_while(|(w, _)| w == weight).count();
+        let (group, tail) = rest.split_at(len);
+        rest = tail;
+        Some(group)
+    })
+}
+
+impl Default for Ranking {
+    /// Definitions with the word's part of speech first, then ones that aren't
+    /// archaic or rare, then ones usually written in kana for kana words and
+    /// the other way round
+    fn default() -> Self {
+        let rule = |predicate, weight| RankingRule { predicate, weight };
+        Ranking {
+            tiers: vec![
+                vec![rule(Predicate::WordPos, 1)],
+                vec![
+                    rule(Predicate::Flag("&arch;".into()), -1),
+                    rule(Predicate::Flag("&rare;".into()), -1),
+                ],
+                vec![rule(Predicate::Equal(
+                    Box::new(Predicate::Flag("&uk;".into())),
+                    Box::new(Predicate::KanaWord)
+                ), 1)],
+            ]
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::result;
+
+    fn entry(id: i32, pos: &str, flags: &[&str], reading: &str) -> DictionaryEntry {
+        DictionaryEntry {
+            definitions: vec![Definition::new(
+                format!("definition {}", id),
+                vec![pos.into()],
+                flags.iter().map(|flag| flag.to_string()).collect()
+            ).unwrap()],
+            audio: vec![format!("{}.mp3", id)],
+            ..crate::test_util::entry(Source::JMDict(id), &["そした"], &[reading])
+        }
+    }
+
+    #[test]
+    fn test_best() {
+        let word = AnalyzerResult {
+            pos: PartOfSpeech::Conjuction,
+            dict_info: vec![
+                entry(1, "&n;", &[], "しかした"),
+                entry(2, "&conj;", &["&arch;"], "そした"),
+                entry(3, "&conj;", &[], "しかした"),
+                entry(4, "&conj;", &[], "そした"),
+            ],
+            ..result("そした", "ソシタ")
+        };
+
+        let ranking = Ranking::default();
+        let ranked: Vec<Source> = ranking.rank(&word).iter().map(|(_, entry)| entry.source).collect();
+        assert_eq!(ranked, vec![Source::JMDict(3), Source::JMDict(4), Source::JMDict(2), Source::JMDict(1)]);
+
+        let best = ranking.best(&word);
+        assert_eq!(best.definitions.len(), 2);
+        assert_eq!(best.audio, vec!["3.mp3", "4.mp3"]);
+        assert_eq!(best.readings, vec!["そした"]);
+    }
+}
@@ -0,0 +1,128 @@
+use std::{collections::BTreeMap, ops::Range};
+use jp2anki_dict::PartOfSpeech;
+use wasm_bindgen::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::{AnalyzerResult, Occurrence};
+
+const TERMINATORS: [char; 5] = ['。', '！', '？', '!', '?'];
+const BRACKETS: [(char, char); 4] = [('「', '」'), ('『', '』'), ('（', '）'), ('(', ')')];
+
+/// Where a sentence is in a text, without surrounding whitespace
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Sentence {
+    pub byte_span: Range<usize>,
+    pub char_span: Range<usize>
+}
+
+impl Sentence {
+    pub fn text<'a>(&self, text: &'a str) -> &'a str {
+        &text[self.byte_span.clone()]
+    }
+}
+
+fn closing_of(c: char) -> Option<char> {
+    BRACKETS.iter().find(|(open, _)| *open == c).map(|(_, close)| *close)
+}
+
+/// Splits a text into sentences after runs of sentence-final punctuation like
+/// 。 or ！？ and at line breaks, so every line of dialogue is a sentence.
+/// Punctuation inside 「」, 『』 and （） doesn't end the sentence around the
+/// quote, but a quote followed directly by another one is a sentence of its
+/// own. Ellipses like …… never end a sentence by themselves.
+pub fn split_sentences(text: &str) -> Vec<Sentence> {
+    let mut spans = Vec::new();
+    // Closing brackets of the quotes the current position is in, innermost last
+    let mut open: Vec<char> = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let mut end = i + c.len_utf8();
+        if c == '\n' {
+            // A missing closing bracket doesn't swallow the rest of the text
+            open.clear();
+            spans.push(start..i);
+            start = end;
+        } else if TERMINATORS.contains(&c) && open.is_empty() {
+            while let Some((j, next)) = chars.next_if(|(_, next)| TERMINATORS.contains(next)) {
+                end = j + next.len_utf8();
+            }
+            spans.push(start..end);
+            start = end;
+        } else if let Some(close) = closing_of(c) {
+            open.push(close);
+        } else if let Some(depth) = open.iter().rposition(|&close| close == c) {
+            open.truncate(depth);
+            if open.is_empty() && chars.peek().is_some_and(|&(_, next)| closing_of(next).is_some()) {
+                spans.push(start..end);
+                start = end;
+            }
+        }
+    }
+    spans.push(start..text.len());
+
+    let mut sentences = Vec::new();
+    let (mut byte_pos, mut char_pos) = (0, 0);
+    for span in spans {
+        let untrimmed = &text[span.clone()];
+        let trimmed = untrimmed.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let byte_start = span.start + untrimmed.len() - untrimmed.trim_start().len();
+        let char_start = char_pos + text[byte_pos..byte_start].chars().count();
+        let char_end = char_start + trimmed.chars().count();
+        byte_pos = byte_start + trimmed.len();
+        char_pos = char_end;
+        sentences.push(Sentence { byte_span: byte_start..byte_pos, char_span: char_start..char_end });
+    }
+    sentences
+}
+
+/// Sentences of a text as objects with a byte and a char span
+#[wasm_bindgen]
+pub fn segment_sentences(text: &str) -> JsValue {
+    JsValue::from_serde(&split_sentences(text)).unwrap()
+}
+
+/// Occurrences of the words other than punctuation in each sentence of an
+/// analyzed document, by the byte offset of the sentence
+pub(crate) fn group_by_sentence(results: &[AnalyzerResult]) -> BTreeMap<usize, Vec<(&AnalyzerResult, &Occurrence)>> {
+    let mut sentences: BTreeMap<usize, Vec<(&AnalyzerResult, &Occurrence)>> = BTreeMap::new();
+    for result in results.iter().filter(|result| result.pos != PartOfSpeech::Symbol) {
+        for occurrence in &result.occurrences {
+            let sentence_start = occurrence.byte_span.start - occurrence.sentence_offset;
+            sentences.entry(sentence_start).or_default().push((result, occurrence));
+        }
+    }
+    sentences
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sentences(text: &str) -> Vec<&str> {
+        split_sentences(text).iter().map(|sentence| sentence.text(text)).collect()
+    }
+
+    #[test]
+    fn test_split_sentences() {
+        assert_eq!(sentences("猫が好き。犬も！？ 本当？"), vec!["猫が好き。", "犬も！？", "本当？"]);
+        assert_eq!(sentences("「行こう。『待って！』と言った」と彼は言った。"), vec!["「行こう。『待って！』と言った」と彼は言った。"]);
+        assert_eq!(sentences("えっと……そうなの。。。"), vec!["えっと……そうなの。。。"]);
+        assert_eq!(sentences("「おはよう」\n「おはよう。」「元気？」\n\n（笑）"), vec!["「おはよう」", "「おはよう。」", "「元気？」", "（笑）"]);
+        assert_eq!(sentences("「閉じない。\n次の行。"), vec!["「閉じない。", "次の行。"]);
+        assert_eq!(sentences("  \n"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_spans() {
+        let text = "　猫だ。 犬";
+        let spans = split_sentences(text);
+        assert_eq!(spans[0], Sentence { byte_span: 3..12, char_span: 1..4 });
+        assert_eq!(spans[1], Sentence { byte_span: 13..16, char_span: 5..6 });
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use jp2anki_dict::{JlptLevel, PartOfSpeech};
+use wasm_bindgen::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::{AnalyzerResult, furigana::is_kanji, sentence::group_by_sentence, word_list::WordList};
+
+/// Upper frequency ranks of the bands in `TextReport::frequency_bands`
+pub const FREQUENCY_BANDS: [u32; 5] = [1000, 2000, 5000, 10000, 20000];
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WordCount {
+    /// Distinct words
+    pub words: u32,
+    /// Times any of the words appear in the text
+    pub occurrences: u32
+}
+
+impl WordCount {
+    fn add(&mut self, result: &AnalyzerResult) {
+        self.words += 1;
+        self.occurrences += result.count;
+    }
+}
+
+/// How many of the analyzed words are at each JLPT level
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct JlptCounts {
+    pub levels: BTreeMap<JlptLevel, WordCount>,
+    /// Words without a level, including ones missing from the dictionary
+    pub unleveled: WordCount
+}
+
+impl JlptCounts {
+    pub fn new<'a>(results: impl IntoIterator<Item = &'a AnalyzerResult>) -> Self {
+        let mut counts = JlptCounts::default();
+        for result in results {
+            match result.jlpt_level {
+                Some(level) => counts.levels.entry(level).or_default().add(result),
+                None => counts.unleveled.add(result)
+            }
+        }
+        counts
+    }
+}
+
+/// Words ranked at most `max_rank` and below the previous band's rank
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrequencyBand {
+    /// `None` for the band of words ranked beyond the last of `FREQUENCY_BANDS`
+    pub max_rank: Option<u32>,
+    pub count: WordCount
+}
+
+/// Overview of how hard a text is for a learner
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TextReport {
+    /// Tokens of the text, leaving out punctuation and ignored words
+    pub total_tokens: u32,
+    pub unique_tokens: u32,
+    pub known_tokens: u32,
+    /// Share of the tokens that are known words, from 0 to 1
+    pub known_coverage: f64,
+    /// Sentences with at least one word
+    pub sentences: u32,
+    /// Sentences in which every word is known
+    pub known_sentences: u32,
+    /// Average number of tokens per sentence
+    pub mean_sentence_length: f64,
+    pub jlpt: JlptCounts,
+    pub frequency_bands: Vec<FrequencyBand>,
+    pub unranked: WordCount,
+    /// Distinct kanji in the text
+    pub kanji_count: u32,
+    /// Kanji of the text that don't appear in any known word, in order of appearance
+    pub unknown_kanji: Vec<char>
+}
+
+impl TextReport {
+    /// Summarizes the unfiltered results of analyzing a text
+    pub fn new(results: &[AnalyzerResult], known_words: &WordList) -> Self {
+        let words: Vec<&AnalyzerResult> = results.iter()
+            .filter(|result| result.pos != PartOfSpeech::Symbol)
+            .collect();
+
+        let total_tokens: u32 = words.iter().map(|result| result.count).sum();
+        let known_tokens = words.iter().filter(|result| result.known).map(|result| result.count).sum();
+
+        let sentences = group_by_sentence(results);
+        let known_sentences = sentences.values()
+            .filter(|occurrences| occurrences.iter().all(|(result, _)| result.known))
+            .count();
+
+        let mut frequency_bands: Vec<FrequencyBand> = FREQUENCY_BANDS.iter()
+            .map(|&max_rank| Some(max_rank))
+            .chain([None])
+            .map(|max_rank| FrequencyBand { max_rank, count: WordCount::default() })
+            .collect();
+        let mut unranked = WordCount::default();
+        for result in words.iter().copied() {
+            match result.frequency_rank {
+                Some(rank) => {
+                    let band = FREQUENCY_BANDS.iter().take_while(|&&max_rank| rank > max_rank).count();
+                    frequency_bands[band].count.add(result);
+                },
+                None => unranked.add(result)
+            }
+        }
+
+        let known_kanji: HashSet<char> = known_words.iter().flat_map(str::chars).collect();
+        let mut kanji = BTreeSet::new();
+        let mut unknown_kanji = Vec::new();
+        let mut occurrences: Vec<_> = words.iter().flat_map(|result| &result.occurrences).collect();
+        occurrences.sort_by_key(|occurrence| occurrence.byte_span.start);
+        for c in occurrences.iter().flat_map(|occurrence| occurrence.surface.chars()).filter(|&c| is_kanji(c)) {
+            if kanji.insert(c) && !known_kanji.contains(&c) {
+                unknown_kanji.push(c);
+            }
+        }
+
+        TextReport {
+            total_tokens,
+            unique_tokens: words.len() as u32,
+            known_tokens,
+            known_coverage: match total_tokens {
+                0 => 0.0,
+                total => known_tokens as f64 / total as f64
+            },
+            sentences: sentences.len() as u32,
+            known_sentences: known_sentences as u32,
+            mean_sentence_length: match sentences.len() {
+                0 => 0.0,
+                n => total_tokens as f64 / n as f64
+            },
+            jlpt: JlptCounts::new(words.iter().copied()),
+            frequency_bands,
+            unranked,
+            kanji_count: kanji.len() as u32,
+            unknown_kanji
+        }
+    }
+}
+
+/// Counts the words of each JLPT level in the results of `TextAnalyzer::analyze`
+#[wasm_bindgen]
+pub fn jlpt_counts(results: JsValue) -> JsValue {
+    let results: Vec<AnalyzerResult> = results.into_serde().unwrap();
+    JsValue::from_serde(&JlptCounts::new(&results)).unwrap()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::occurrence;
+
+    use super::*;
+
+    fn result(word: &str, count: u32, jlpt_level: Option<JlptLevel>) -> AnalyzerResult {
+        AnalyzerResult {
+            pos: if word == "。" { PartOfSpeech::Symbol } else { PartOfSpeech::Noun },
+            count,
+            jlpt_level,
+            occurrences: vec![occurrence(word, 0, 0, 0)],
+            ..crate::test_util::result(word, word)
+        }
+    }
+
+    #[test]
+    fn test_jlpt_counts() {
+        let counts = JlptCounts::new(&[
+            result("猫", 3, Some(JlptLevel::N5)),
+            result("犬", 1, Some(JlptLevel::N5)),
+            result("鼠", 2, Some(JlptLevel::N1)),
+            result("ニャー", 4, None),
+        ]);
+        assert_eq!(counts.levels[&JlptLevel::N5], WordCount { words: 2, occurrences: 4 });
+        assert_eq!(counts.levels[&JlptLevel::N1], WordCount { words: 1, occurrences: 2 });
+        assert!(!counts.levels.contains_key(&JlptLevel::N3));
+        assert_eq!(counts.unleveled, WordCount { words: 1, occurrences: 4 });
+    }
+
+    #[test]
+    fn test_text_report() {
+        let mut results = vec![
+            result("猫", 3, Some(JlptLevel::N5)),
+            result("子猫", 1, None),
+            result("鼠", 4, None),
+            result("。", 2, None),
+        ];
+        results[0].known = true;
+        results[0].frequency_rank = Some(500);
+        results[2].frequency_rank = Some(30000);
+
+        let report = TextReport::new(&results, &WordList::from_text("猫"));
+        assert_eq!(report.total_tokens, 8);
+        assert_eq!(report.unique_tokens, 3);
+        assert_eq!(report.known_coverage, 3.0 / 8.0);
+        assert_eq!(report.sentences, 1);
+        assert_eq!(report.known_sentences, 0);
+        assert_eq!(report.mean_sentence_length, 8.0);
+        assert_eq!(report.frequency_bands[0].count, WordCount { words: 1, occurrences: 3 });
+        assert_eq!(report.frequency_bands[5], FrequencyBand { max_rank: None, count: WordCount { words: 1, occurrences: 4 } });
+        assert_eq!(report.unranked, WordCount { words: 1, occurrences: 1 });
+        assert_eq!(report.kanji_count, 3);
+        assert_eq!(report.unknown_kanji, vec!['子', '鼠']);
+    }
+}
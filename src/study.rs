@@ -0,0 +1,263 @@
+use std::{cmp::Reverse, collections::{BinaryHeap, HashMap}};
+use jp2anki_dict::{DictionaryEntry, PartOfSpeech};
+use serde::{Serialize, Deserialize};
+
+use crate::{AnalyzerResult, Occurrence, WordKey, ranking::Ranking, sentence::group_by_sentence};
+
+/// A word to learn and how far learning it and every word before it gets
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LearningStep {
+    pub word: String,
+    pub reading: String,
+    pub pos: PartOfSpeech,
+    /// Occurrences of the word in all documents
+    pub count: u32,
+    /// Sentences that become fully known with this word, which were i+1
+    /// sentences with it as the unknown word
+    pub unlocked_sentences: u32,
+    /// Share of all sentences that are fully known after learning this
+    /// word, from 0 to 1
+    pub coverage: f64
+}
+
+/// Unknown words in the order that makes the most sentences comprehensible
+/// soonest, with the cumulative coverage curve
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LearningPlan {
+    pub total_sentences: u32,
+    /// Share of the sentences that are fully known before learning anything
+    pub initial_coverage: f64,
+    pub steps: Vec<LearningStep>
+}
+
+struct Candidate {
+    key: WordKey,
+    count: u32,
+    sentences: Vec<usize>,
+    // Sentences in which this is the only unknown word left
+    unlocks: u32,
+    learned: bool
+}
+
+/// Greedily picks the unknown word that completes the most i+1 sentences
+/// next, preferring more frequent words on ties and while no sentence is
+/// one word away from being known. Takes the unfiltered results of
+/// analyzing each document, with known words marked. Particles and
+/// auxiliary verbs are left out, see `counts_as_unknown`.
+pub fn learning_plan(documents: &[Vec<AnalyzerResult>], max_words: Option<usize>) -> LearningPlan {
+    let mut candidate_ids: HashMap<WordKey, usize> = HashMap::new();
+    let mut candidates: Vec<Candidate> = Vec::new();
+    // Unknown words of each sentence
+    let mut sentence_words: Vec<Vec<usize>> = Vec::new();
+
+    for results in documents {
+        for occurrences in group_by_sentence(results).into_values() {
+            let sentence = sentence_words.len();
+            let mut words = Vec::new();
+            for (result, _) in occurrences.into_iter().filter(|(result, _)| counts_as_unknown(result)) {
+                let key = (result.word.clone(), result.reading.clone(), result.pos);
+                let candidate = *candidate_ids.entry(key.clone()).or_insert_with(|| {
+                    candidates.push(Candidate { key, count: 0, sentences: Vec::new(), unlocks: 0, learned: false });
+                    candidates.len() - 1
+                });
+                candidates[candidate].count += 1;
+                if !words.contains(&candidate) {
+                    words.push(candidate);
+                    candidates[candidate].sentences.push(sentence);
+                }
+            }
+            sentence_words.push(words);
+        }
+    }
+
+    let mut remaining: Vec<usize> = sentence_words.iter().map(Vec::len).collect();
+    for words in sentence_words.iter().filter(|words| words.len() == 1) {
+        candidates[words[0]].unlocks += 1;
+    }
+
+    let total_sentences = sentence_words.len();
+    let mut known_sentences = remaining.iter().filter(|&&n| n == 0).count();
+    let share = |known: usize| match total_sentences {
+        0 => 0.0,
+        total => known as f64 / total as f64
+    };
+    let initial_coverage = share(known_sentences);
+
+    // Words are pushed again whenever their unlocks grow, so entries with
+    // fewer unlocks than the word has now are stale and skipped
+    let mut queue: BinaryHeap<(u32, u32, Reverse<usize>)> = candidates.iter()
+        .enumerate()
+        .map(|(i, candidate)| (candidate.unlocks, candidate.count, Reverse(i)))
+        .collect();
+
+    let mut steps = Vec::new();
+    while steps.len() < max_words.unwrap_or(usize::MAX) {
+        let Some((unlocks, _, Reverse(best))) = queue.pop() else { break };
+        if candidates[best].learned || unlocks != candidates[best].unlocks {
+            continue;
+        }
+
+        candidates[best].learned = true;
+        let unlocked = candidates[best].unlocks;
+        for sentence in std::mem::take(&mut candidates[best].sentences) {
+            remaining[sentence] -= 1;
+            match remaining[sentence] {
+                0 => known_sentences += 1,
+                1 => {
+                    let last = sentence_words[sentence].iter()
+                        .find(|&&word| !candidates[word].learned)
+                        .copied();
+                    if let Some(last) = last {
+                        candidates[last].unlocks += 1;
+                        queue.push((candidates[last].unlocks, candidates[last].count, Reverse(last)));
+                    }
+                },
+                _ => ()
+            }
+        }
+
+        let (word, reading, pos) = candidates[best].key.clone();
+        steps.push(LearningStep {
+            word,
+            reading,
+            pos,
+            count: candidates[best].count,
+            unlocked_sentences: unlocked,
+            coverage: share(known_sentences)
+        });
+    }
+
+    LearningPlan { total_sentences: total_sentences as u32, initial_coverage, steps }
+}
+
+/// A sentence in which every word but one is known
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MinedSentence {
+    pub sentence: String,
+    pub word: String,
+    pub reading: String,
+    pub pos: PartOfSpeech,
+    /// Where the unknown word is in the text and the sentence
+    pub occurrence: Occurrence,
+    /// The best ranked dictionary entry of the word, if it has any
+    pub entry: Option<DictionaryEntry>
+}
+
+/// Finds the i+1 sentences of a document, in order of appearance and
+/// without repeating identical sentences. Takes the unfiltered results of
+/// analyzing the document, with known words marked. Unknown particles and
+/// auxiliary verbs don't keep a sentence from being i+1.
+pub fn mine_sentences(results: &[AnalyzerResult], ranking: &Ranking) -> Vec<MinedSentence> {
+    let mut mined: Vec<MinedSentence> = Vec::new();
+    for occurrences in group_by_sentence(results).into_values() {
+        let mut unknown = occurrences.iter().filter(|(result, _)| counts_as_unknown(result));
+        let Some(&(result, occurrence)) = unknown.next() else { continue };
+        if unknown.any(|(other, _)| !std::ptr::eq(*other, result)) {
+            continue;
+        }
+        let sentence = &result.sentences[occurrence.sentence];
+        if mined.iter().any(|mined| &mined.sentence == sentence) {
+            continue;
+        }
+        mined.push(MinedSentence {
+            sentence: sentence.clone(),
+            word: result.word.clone(),
+            reading: result.reading.clone(),
+            pos: result.pos,
+            occurrence: occurrence.clone(),
+            entry: ranking.rank(result).first().map(|(_, entry)| (*entry).clone())
+        });
+    }
+    mined
+}
+
+// Particles and auxiliary verbs like が and だ are learned from the
+// sentences they appear in rather than as words of their own
+fn counts_as_unknown(result: &AnalyzerResult) -> bool {
+    !result.known && !matches!(result.pos, PartOfSpeech::Particle | PartOfSpeech::AuxiliaryVerb)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{entry, occurrence};
+
+    // A word occurring once in each of the given sentences, which start 100 bytes apart
+    fn result(word: &str, known: bool, sentences: &[usize]) -> AnalyzerResult {
+        AnalyzerResult {
+            count: sentences.len() as u32,
+            known,
+            sentences: sentences.iter().map(|sentence| format!("sentence {}", sentence)).collect(),
+            occurrences: sentences.iter()
+                .enumerate()
+                .map(|(i, &sentence)| occurrence(word, i, sentence * 100, 10))
+                .collect(),
+            ..crate::test_util::result(word, word)
+        }
+    }
+
+    #[test]
+    fn test_learning_plan() {
+        let documents = vec![vec![
+            result("は", true, &[0, 1, 2, 3]),
+            result("猫", false, &[0, 1, 2]),
+            result("犬", false, &[1, 2]),
+            result("鳥", false, &[3]),
+            result("鼠", false, &[3]),
+        ]];
+
+        let plan = learning_plan(&documents, None);
+        assert_eq!(plan.total_sentences, 4);
+        assert_eq!(plan.initial_coverage, 0.0);
+        let order: Vec<(&str, u32)> = plan.steps.iter()
+            .map(|step| (step.word.as_str(), step.unlocked_sentences))
+            .collect();
+        assert_eq!(order, vec![("猫", 1), ("犬", 2), ("鳥", 0), ("鼠", 1)]);
+        assert_eq!(plan.steps[1].coverage, 0.75);
+        assert_eq!(plan.steps[3].coverage, 1.0);
+
+        assert_eq!(learning_plan(&documents, Some(1)).steps.len(), 1);
+    }
+
+    #[test]
+    fn test_mine_sentences() {
+        let mut results = vec![
+            result("は", true, &[0, 1, 2, 3]),
+            result("猫", false, &[0, 1, 2]),
+            result("犬", true, &[1, 2]),
+            result("鳥", false, &[1, 3]),
+        ];
+        results[1].dict_info = vec![entry(jp2anki_dict::Source::JMDict(1), &["猫"], &["ねこ"])];
+
+        let mined = mine_sentences(&results, &Ranking::default());
+        let sentences: Vec<(&str, &str)> = mined.iter()
+            .map(|mined| (mined.sentence.as_str(), mined.word.as_str()))
+            .collect();
+        assert_eq!(sentences, vec![("sentence 0", "猫"), ("sentence 2", "猫"), ("sentence 3", "鳥")]);
+        assert_eq!(mined[0].occurrence.byte_span, 10..13);
+        assert_eq!(mined[0].entry.as_ref().unwrap().readings, vec!["ねこ"]);
+        assert!(mined[2].entry.is_none());
+    }
+
+    #[test]
+    fn test_unknown_particles() {
+        let results = vec![
+            AnalyzerResult { pos: PartOfSpeech::Particle, ..result("が", false, &[0, 1]) },
+            AnalyzerResult { pos: PartOfSpeech::AuxiliaryVerb, ..result("だ", false, &[0]) },
+            result("猫", false, &[0]),
+            result("犬", false, &[1]),
+            result("鳥", false, &[1]),
+        ];
+
+        let mined = mine_sentences(&results, &Ranking::default());
+        assert_eq!(mined.len(), 1);
+        assert_eq!((mined[0].sentence.as_str(), mined[0].word.as_str()), ("sentence 0", "猫"));
+
+        let plan = learning_plan(&[results], None);
+        let words: Vec<&str> = plan.steps.iter().map(|step| step.word.as_str()).collect();
+        assert_eq!(words, vec!["猫", "犬", "鳥"]);
+        assert_eq!(plan.steps[0].unlocked_sentences, 1);
+    }
+}
@@ -0,0 +1,31 @@
+//! Builders for the fixtures of tests in this and the dependent crates
+
+use jp2anki_dict::PartOfSpeech;
+
+use crate::{AnalyzerResult, Occurrence};
+
+pub use jp2anki_dict::test_util::entry;
+
+/// A noun occurring once. Tests set the rest with struct update syntax.
+pub fn result(word: &str, reading: &str) -> AnalyzerResult {
+    AnalyzerResult {
+        word: word.into(),
+        pos: PartOfSpeech::Noun,
+        reading: reading.into(),
+        count: 1,
+        ..Default::default()
+    }
+}
+
+/// An occurrence starting `sentence_offset` bytes into the sentence that
+/// starts at `sentence_start` in the text
+pub fn occurrence(surface: &str, sentence: usize, sentence_start: usize, sentence_offset: usize) -> Occurrence {
+    let start = sentence_start + sentence_offset;
+    Occurrence {
+        surface: surface.into(),
+        byte_span: start..start + surface.len(),
+        sentence,
+        sentence_offset,
+        ..Default::default()
+    }
+}
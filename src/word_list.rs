@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use jp2anki_dict::normalize::normalize;
+use serde::{Serialize, Deserialize};
+
+/// Set of lemmas, such as the words a learner already knows. Words are
+/// compared in their normalized form, so listing ネコ also covers ねこ.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct WordList {
+    // Normalized form to the word as it was added
+    words: BTreeMap<String, String>
+}
+
+impl WordList {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Reads a list with one word per line, as exported by `to_text` or from
+    /// a spreadsheet or Anki deck. Only the first tab or comma separated
+    /// column is used, and blank lines and lines starting with `#` are skipped.
+    pub fn from_text(text: &str) -> Self {
+        let mut list = WordList::new();
+        list.import(text);
+        list
+    }
+
+    /// Adds the words of a list in the format read by `from_text`, returning
+    /// the number of words that weren't in the list yet
+    pub fn import(&mut self, text: &str) -> usize {
+        text.lines()
+            .map(|line| line.split(['\t', ',']).next().unwrap_or("").trim())
+            .filter(|word| !word.is_empty() && !word.starts_with('#'))
+            .filter(|word| self.insert(word))
+            .count()
+    }
+
+    pub fn to_text(&self) -> String {
+        self.words.values()
+            .map(|word| format!("{}\n", word))
+            .collect()
+    }
+
+    /// Returns whether the word wasn't in the list yet
+    pub fn insert(&mut self, word: &str) -> bool {
+        self.words.insert(normalize(word), word.to_owned()).is_none()
+    }
+
+    /// Returns whether the word was in the list
+    pub fn remove(&mut self, word: &str) -> bool {
+        self.words.remove(&normalize(word)).is_some()
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains_key(&normalize(word))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.words.values().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_list() {
+        let mut list = WordList::from_text("# known words\n猫\tねこ\nイヌ,dog\n\n猫\n");
+        assert_eq!(list.len(), 2);
+        assert!(list.contains("いぬ"));
+        assert_eq!(list.import("鳥\n犬"), 2);
+        assert!(list.remove("猫"));
+        assert!(!list.contains("猫"));
+        assert_eq!(list.to_text(), "イヌ\n犬\n鳥\n");
+    }
+}
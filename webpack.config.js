@@ -36,6 +36,11 @@ module.exports = {
         new HtmlWebpackPlugin({ title: "JP2Anki", template: "template.html" }),
         new WasmPackPlugin({ crateDirectory: path.resolve(__dirname, '.') }),
         new MiniCssExtractPlugin(),
+        // The dictionary isn't checked in, since it has to be built with the
+        // current format version. Download JMdict_e from the EDRDG and run
+        //   cd jp2anki-dict-builder && cargo +nightly run --release -- -j JMDict_e
+        // optionally with `-t <WaniKani API token>`, `-f <frequency list>` and
+        // `-l <JLPT list>`.
         new CopyPlugin({
             patterns: [
                 path.resolve(__dirname, "jp2anki-dict-builder", "dictionary.dat"),
@@ -9,12 +9,140 @@ export type PartOfSpeech = "Noun" | "Prefix" | "Verb" | "Adjective" | "Adverb"
     | "Adnominal" | "Conjuction" | "Particle" | "AuxiliaryVerb"
     | "Exclamation" | "Symbol" | "Filler" | "Other";
 
+export type JlptLevel = "N5" | "N4" | "N3" | "N2" | "N1";
+
+export interface Span {
+    start: number,
+    end: number
+}
+
+export interface Occurrence {
+    surface: string,
+    inflection: string,
+    byte_span: Span,
+    char_span: Span,
+    sentence: number,
+    sentence_offset: number
+}
+
 export interface AnalyzerResult {
     word: string,
     pos: PartOfSpeech,
     reading: string,
     count: number,
-    dict_info: DictionaryEntry[]
+    dict_info: DictionaryEntry[],
+    sentences: string[],
+    occurrences: Occurrence[],
+    children: AnalyzerResult[],
+    reading_matches: number,
+    known: boolean,
+    frequency_rank: number | null,
+    priorities: string[],
+    jlpt_level: JlptLevel | null,
+    deinflection: Deinflection | null,
+    best: BestSelection | null
+}
+
+export interface Deinflection {
+    term: string,
+    reasons: string[]
+}
+
+export type SortKey = "Count" | "FirstAppearance" | "FrequencyRank" | "JlptLevel";
+
+export interface AnalysisOptions {
+    exclude_pos?: PartOfSpeech[],
+    exclude_known?: boolean,
+    min_count?: number,
+    max_frequency_rank?: number | null,
+    max_results?: number | null,
+    sort_by?: SortKey,
+    ranking?: Ranking | null
+}
+
+export type Predicate = { "Flag": string } | { "Pos": PartOfSpeech } | "WordPos"
+    | { "Source": "WaniKani" | "JMDict" } | "KanaWord" | "WordReading" | "WordForm"
+    | { "Not": Predicate } | { "All": Predicate[] } | { "Any": Predicate[] }
+    | { "Equal": [Predicate, Predicate] };
+
+export interface RankingRule {
+    predicate: Predicate,
+    weight: number
+}
+
+export interface Ranking {
+    tiers: RankingRule[][]
+}
+
+export interface BestSelection {
+    definitions: Definition[],
+    examples: Example[],
+    audio: string[],
+    readings: string[]
+}
+
+export interface WordCount {
+    words: number,
+    occurrences: number
+}
+
+export interface JlptCounts {
+    levels: { [level in JlptLevel]?: WordCount },
+    unleveled: WordCount
+}
+
+export interface FrequencyBand {
+    max_rank: number | null,
+    count: WordCount
+}
+
+export interface TextReport {
+    total_tokens: number,
+    unique_tokens: number,
+    known_tokens: number,
+    known_coverage: number,
+    sentences: number,
+    known_sentences: number,
+    mean_sentence_length: number,
+    jlpt: JlptCounts,
+    frequency_bands: FrequencyBand[],
+    unranked: WordCount,
+    kanji_count: number,
+    unknown_kanji: string[]
+}
+
+export interface Analysis {
+    words: AnalyzerResult[],
+    report: TextReport
+}
+
+export interface LearningStep {
+    word: string,
+    reading: string,
+    pos: PartOfSpeech,
+    count: number,
+    unlocked_sentences: number,
+    coverage: number
+}
+
+export interface LearningPlan {
+    total_sentences: number,
+    initial_coverage: number,
+    steps: LearningStep[]
+}
+
+export interface MinedSentence {
+    sentence: string,
+    word: string,
+    reading: string,
+    pos: PartOfSpeech,
+    occurrence: Occurrence,
+    entry: DictionaryEntry | null
+}
+
+export interface Sentence {
+    byte_span: Span,
+    char_span: Span
 }
 
 export interface DictionaryEntry {
@@ -23,7 +151,10 @@ export interface DictionaryEntry {
     definitions: Definition[],
     audio: string[],
     readings: string[],
-    examples: Example[]
+    examples: Example[],
+    priorities: { [form: string]: string[] },
+    frequency_rank: number | null,
+    jlpt_level: JlptLevel | null
 }
 
 export interface Definition {
@@ -53,7 +184,7 @@ async function loadTextAnalyzer(): Promise<TextAnalyzer> {
     );
 }
 
-export function useTextAnalyzer(text: string): AnalyzerResult[] | null {
+export function useTextAnalyzer(text: string, options?: AnalysisOptions): AnalyzerResult[] | null {
     const [analyzer, set_analyzer] = useState<TextAnalyzer | null>(null);
     const [result, set_result] = useState<AnalyzerResult[] | null>(null);
     useEffect(() => {
@@ -61,10 +192,10 @@ export function useTextAnalyzer(text: string): AnalyzerResult[] | null {
     }, []);
     useEffect(() => {
         if (analyzer != null) {
-            const res = analyzer.analyze(text);
+            const res = analyzer.analyze_with_options(text, options);
             console.log(res);
             set_result(res);
         }
-    }, [analyzer, text])
+    }, [analyzer, text, options])
     return result;
 }
\ No newline at end of file
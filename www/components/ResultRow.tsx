@@ -1,6 +1,5 @@
 import React from 'react';
 import { AnalyzerResult } from '../analyzer';
-import { Filter, find_best_definitions } from '../filter';
 
 export function ResultHeader(): JSX.Element {
     return <tr>
@@ -14,15 +13,14 @@ export function ResultHeader(): JSX.Element {
     </tr>
 }
 
-export function ResultRow({ result, filter }: {
-    result: AnalyzerResult,
-    filter: Filter
+export function ResultRow({ result }: {
+    result: AnalyzerResult
 }): JSX.Element {
-    let info = find_best_definitions(result, filter);
+    let info = result.best ?? { definitions: [], examples: [], audio: [], readings: [result.reading] };
     return <tr key={result.word}>
         <td>{result.word}</td>
         <td>{result.count}</td>
-        <td>{[...info.readings].join(', ')}</td>
+        <td>{info.readings.join(', ')}</td>
         <td>{result.pos}</td>
         <td><div className="content">{info.definitions.map((def, i) => <p>{i+1}. {def.text}</p>)}</div></td>
         <td><div className="content">{info.examples.map(ex => <p>{ex.ja}<br/>{ex.en}</p>)}</div></td>
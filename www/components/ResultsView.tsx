@@ -1,11 +1,9 @@
 import React from 'react';
 import { AnalyzerResult } from '../analyzer';
-import { Filter } from '../filter';
 import { ResultHeader, ResultRow } from './ResultRow';
 
-export default function ResultsView({results, filter}: {
-    results: AnalyzerResult[],
-    filter: Filter
+export default function ResultsView({results}: {
+    results: AnalyzerResult[]
 }): JSX.Element {
     return <div className="table-container">
         <table className='table is-hoverable is-fullwidth is-bordered'>
@@ -13,7 +11,7 @@ export default function ResultsView({results, filter}: {
                 <ResultHeader/>
             </thead>
             <tbody>
-                {results.map(row => <ResultRow result={row} filter={filter}/>)}
+                {results.map(row => <ResultRow result={row}/>)}
             </tbody>
         </table>
     </div>